@@ -34,6 +34,27 @@ impl AABB {
         }
     }
 
+    // Creates the box enclosing both input boxes
+    pub fn from_boxes(box0: &AABB, box1: &AABB) -> Self {
+        Self {
+            x: Interval::from_intervals(&box0.x, &box1.x),
+            y: Interval::from_intervals(&box0.y, &box1.y),
+            z: Interval::from_intervals(&box0.z, &box1.z),
+        }
+    }
+
+    // Returns a copy with every side at least delta wide, so flat primitives still get a box the
+    // slab test can hit
+    pub fn pad_to_minimums(&self, delta: f64) -> Self {
+        let pad = |interval: Interval| if interval.size() < delta { interval.expand(delta) } else { interval };
+
+        Self {
+            x: pad(self.x),
+            y: pad(self.y),
+            z: pad(self.z),
+        }
+    }
+
     pub fn axis_interval(&self, axis: Axis) -> Interval {
         match axis {
             Axis::X => self.x,
@@ -42,7 +63,33 @@ impl AABB {
         }
     }
 
-    pub fn hit(&self, ray: Ray, ray_t: &mut Interval) -> bool {
+    // Returns the axis along which the box has the largest extent
+    pub fn longest_axis(&self) -> Axis {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { Axis::X } else { Axis::Z }
+        } else if self.y.size() > self.z.size() {
+            Axis::Y
+        } else {
+            Axis::Z
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.x.size();
+        let dy = self.y.size();
+        let dz = self.z.size();
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        Vec3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    pub fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> bool {
         let ray_orig = ray.origin;
         let ray_dir = ray.direction;
 
@@ -57,8 +104,8 @@ impl AABB {
                 if t0 > ray_t.min { ray_t.min = t0; }
                 if t1 < ray_t.max { ray_t.max = t1; }
             } else {
-                if t0 > ray_t.min { ray_t.min = t1; }
-                if t1 < ray_t.max { ray_t.max = t0; }
+                if t1 > ray_t.min { ray_t.min = t1; }
+                if t0 < ray_t.max { ray_t.max = t0; }
             }
 
             if ray_t.max <= ray_t.min {
@@ -68,4 +115,7 @@ impl AABB {
 
         true
     }
+
+    pub const EMPTY: Self = Self { x: Interval::EMPTY, y: Interval::EMPTY, z: Interval::EMPTY };
+    pub const UNIVERSE: Self = Self { x: Interval::UNIVERSE, y: Interval::UNIVERSE, z: Interval::UNIVERSE };
 }
\ No newline at end of file
@@ -0,0 +1,91 @@
+use crate::image::Image;
+use crate::ray::Ray;
+use crate::sky::{Sky, Sun};
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Colors of the default sky
+pub const SKY_HORIZON: Color = Color::splat(1.0);
+pub const SKY_ZENITH: Color = Vec3 { x: 0.5, y: 0.7, z: 1.0 };
+
+// Radiance seen along rays that escape the scene
+#[derive(Clone)]
+pub enum Background {
+    Solid(Color),
+    // Blend from horizon to zenith color by the height of the ray direction
+    Gradient { horizon: Color, zenith: Color },
+    Environment(EnvironmentMap),
+    Sky(Sky),
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { horizon, zenith } => {
+                let unit_direction = ray.direction.unit_vector();
+                let a = 0.5 * (unit_direction.y + 1.0);
+                (1.0 - a) * *horizon + a * *zenith
+            }
+            Background::Environment(map) => map.lookup(&ray.direction),
+            Background::Sky(sky) => sky.radiance(&ray.direction),
+        }
+    }
+
+    // Bright part of the background to sample directly, like a light
+    pub fn sun(&self) -> Option<&Sun> {
+        match self {
+            Background::Sky(sky) => Some(sky.sun()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Gradient {
+            horizon: SKY_HORIZON,
+            zenith: SKY_ZENITH,
+        }
+    }
+}
+
+// Equirectangular (latitude/longitude) radiance map surrounding the scene
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    image: Arc<Image>,
+    // Rotation of the map around the world up (y) axis in degrees
+    pub rotation: f64,
+    // Multiplier applied to every looked up radiance value
+    pub intensity: f64,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Arc<Image>) -> Self {
+        Self {
+            image,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn lookup(&self, direction: &Vec3) -> Color {
+        let d = direction.unit_vector();
+
+        // Rotate the direction the opposite way instead of rotating the map
+        let (sin, cos) = (-self.rotation.to_radians()).sin_cos();
+        let d = Vec3::new(cos * d.x + sin * d.z, d.y, -sin * d.x + cos * d.z);
+
+        // u runs around the y axis starting at -x, v from the bottom pole (0) to the top pole (1)
+        let theta = f64::acos((-d.y).clamp(-1.0, 1.0));
+        let phi = f64::atan2(-d.z, d.x) + PI;
+        let u = phi / (2.0 * PI);
+        let v = theta / PI;
+
+        let x = u * self.image.width() as f64;
+        let y = (1.0 - v) * self.image.height() as f64;
+
+        self.intensity * self.image.sample_bilinear(x, y)
+    }
+}
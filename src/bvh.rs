@@ -0,0 +1,511 @@
+use crate::aabb::{Axis, AABB};
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+pub struct BvhNode {
+    left: Box<dyn Hittable + Send + Sync>,
+    // None when the node wraps a single object
+    right: Option<Box<dyn Hittable + Send + Sync>>,
+    bbox: AABB,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> Self {
+        Self::from_objects(list.objects)
+    }
+
+    fn from_objects(mut objects: Vec<Box<dyn Hittable + Send + Sync>>) -> Self {
+        // Build the bounding box of the span of source objects
+        let bbox = objects.iter().fold(AABB::EMPTY, |bbox, object| AABB::from_boxes(&bbox, &object.bounding_box()));
+
+        let axis = bbox.longest_axis();
+
+        match objects.len() {
+            0 => Self {
+                left: Box::new(HittableList { objects }),
+                right: None,
+                bbox,
+            },
+            1 => Self {
+                left: objects.pop().unwrap(),
+                right: None,
+                bbox,
+            },
+            _ => {
+                objects.sort_by(|a, b| {
+                    let a_axis = a.bounding_box().axis_interval(axis);
+                    let b_axis = b.bounding_box().axis_interval(axis);
+                    a_axis.min.partial_cmp(&b_axis.min).unwrap_or(Ordering::Equal)
+                });
+
+                let (left, right): (Box<dyn Hittable + Send + Sync>, Box<dyn Hittable + Send + Sync>) = if objects.len() == 2 {
+                    let right = objects.pop().unwrap();
+                    (objects.pop().unwrap(), right)
+                } else {
+                    let rest = objects.split_off(objects.len() / 2);
+                    (Box::new(Self::from_objects(objects)), Box::new(Self::from_objects(rest)))
+                };
+
+                Self {
+                    left,
+                    right: Some(right),
+                    bbox,
+                }
+            }
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut box_t = ray_t;
+        if !self.bbox.hit(ray, &mut box_t) {
+            return None;
+        }
+
+        let hit_left = self.left.hit(ray, ray_t);
+
+        let right = match &self.right {
+            Some(right) => right,
+            None => return hit_left,
+        };
+
+        // Only accept right hits closer than whatever the left side found
+        let max = hit_left.as_ref().map_or(ray_t.max, |hit| hit.t);
+
+        right.hit(ray, Interval::new(ray_t.min, max)).or(hit_left)
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        let mut box_t = ray_t;
+        if !self.bbox.hit(ray, &mut box_t) {
+            return 1.0;
+        }
+
+        let left = self.left.transmittance(ray, ray_t);
+        match &self.right {
+            Some(right) if left > 0.0 => left * right.transmittance(ray, ray_t),
+            _ => left,
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Number of buckets centroids are binned into when evaluating SAH splits
+const SAH_BINS: usize = 12;
+// Relative costs of visiting an interior node and intersecting a primitive
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECT_COST: f64 = 1.0;
+// Leaves are allowed to hold this many primitives if SAH says splitting is not worth it
+const MAX_LEAF_SIZE: usize = 4;
+// Bounds the traversal stack; nodes this deep are always made into leaves
+const MAX_DEPTH: usize = 64;
+
+#[derive(Copy, Clone)]
+struct FlatNode {
+    bbox: AABB,
+    // For leaves the index of the first primitive, for interior nodes the index of the second child.
+    // The first child of an interior node always directly follows it.
+    offset: usize,
+    // Number of primitives in a leaf, zero for interior nodes
+    count: usize,
+    axis: Axis,
+}
+
+struct PrimitiveInfo {
+    index: usize,
+    bbox: AABB,
+    centroid: Vec3,
+}
+
+#[derive(Copy, Clone)]
+struct Bin {
+    bbox: AABB,
+    count: usize,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BvhStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub primitive_count: usize,
+    pub max_depth: usize,
+    // Expected cost of a random ray traversing the tree, relative to one primitive intersection
+    pub sah_cost: f64,
+    pub build_time: Duration,
+}
+
+// Bounding volume hierarchy built with binned surface area heuristic splits and stored as a
+// depth-first array of nodes, with primitives reordered so every leaf references a contiguous range
+pub struct FlatBvh {
+    nodes: Vec<FlatNode>,
+    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    stats: BvhStats,
+}
+
+impl FlatBvh {
+    pub fn new(list: HittableList) -> Self {
+        let start = Instant::now();
+
+        let mut prims: Vec<PrimitiveInfo> = list.objects.iter().enumerate().map(|(index, object)| {
+            let bbox = object.bounding_box();
+            PrimitiveInfo { index, bbox, centroid: bbox.centroid() }
+        }).collect();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * prims.len()),
+            objects: Vec::with_capacity(prims.len()),
+            stats: BvhStats { primitive_count: prims.len(), ..Default::default() },
+        };
+
+        if !prims.is_empty() {
+            bvh.build_recursive(&mut prims, 0, 0);
+        }
+
+        // Reorder the primitives to match the order the leaves reference them in
+        let mut objects: Vec<Option<Box<dyn Hittable + Send + Sync>>> = list.objects.into_iter().map(Some).collect();
+        bvh.objects = prims.iter().map(|prim| objects[prim.index].take().unwrap()).collect();
+
+        bvh.stats.sah_cost = bvh.compute_sah_cost();
+        bvh.stats.build_time = start.elapsed();
+
+        bvh
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    // Builds the subtree over prims (which start at offset first in the final primitive order)
+    // and returns the index of its root node
+    fn build_recursive(&mut self, prims: &mut [PrimitiveInfo], first: usize, depth: usize) -> usize {
+        let bbox = prims.iter().fold(AABB::EMPTY, |bbox, prim| AABB::from_boxes(&bbox, &prim.bbox));
+        let centroid_bounds = prims.iter().fold(AABB::EMPTY, |bbox, prim| {
+            AABB::from_boxes(&bbox, &AABB::from_points(prim.centroid, prim.centroid))
+        });
+        let axis = centroid_bounds.longest_axis();
+
+        let node_index = self.nodes.len();
+        self.nodes.push(FlatNode { bbox, offset: first, count: prims.len(), axis });
+
+        self.stats.node_count += 1;
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+
+        let mid = if prims.len() == 1 || depth + 1 >= MAX_DEPTH {
+            None
+        } else {
+            Self::find_split(prims, &bbox, &centroid_bounds, axis)
+        };
+
+        let mid = match mid {
+            Some(mid) => mid,
+            None => {
+                self.stats.leaf_count += 1;
+                return node_index;
+            }
+        };
+
+        let (left, right) = prims.split_at_mut(mid);
+        self.build_recursive(left, first, depth + 1);
+        let second_child = self.build_recursive(right, first + mid, depth + 1);
+
+        let node = &mut self.nodes[node_index];
+        node.offset = second_child;
+        node.count = 0;
+
+        node_index
+    }
+
+    // Partitions prims around the cheapest SAH split and returns the partition point,
+    // or None if the primitives should stay in a single leaf
+    fn find_split(prims: &mut [PrimitiveInfo], bbox: &AABB, centroid_bounds: &AABB, axis: Axis) -> Option<usize> {
+        let extent = centroid_bounds.axis_interval(axis);
+
+        if extent.size() <= 0.0 {
+            // All centroids coincide so no plane can separate them
+            return if prims.len() > MAX_LEAF_SIZE {
+                Some(Self::median_split(prims, axis))
+            } else {
+                None
+            };
+        }
+
+        let bin_of = |centroid: &Vec3| {
+            let offset = (centroid.get_axis(axis) - extent.min) / extent.size();
+            ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+        };
+
+        let mut bins = [Bin { bbox: AABB::EMPTY, count: 0 }; SAH_BINS];
+        for prim in prims.iter() {
+            let bin = &mut bins[bin_of(&prim.centroid)];
+            bin.bbox = AABB::from_boxes(&bin.bbox, &prim.bbox);
+            bin.count += 1;
+        }
+
+        // Sweep from the right to collect the area and count of everything past each split plane
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0; SAH_BINS];
+        let mut acc = Bin { bbox: AABB::EMPTY, count: 0 };
+        for i in (1..SAH_BINS).rev() {
+            acc.bbox = AABB::from_boxes(&acc.bbox, &bins[i].bbox);
+            acc.count += bins[i].count;
+            right_area[i] = if acc.count > 0 { acc.bbox.surface_area() } else { 0.0 };
+            right_count[i] = acc.count;
+        }
+
+        let parent_area = bbox.surface_area();
+        let inv_parent_area = if parent_area > 0.0 { 1.0 / parent_area } else { 0.0 };
+
+        let mut best_cost = f64::INFINITY;
+        let mut best_split = 0;
+        let mut acc = Bin { bbox: AABB::EMPTY, count: 0 };
+        for split in 0..SAH_BINS - 1 {
+            acc.bbox = AABB::from_boxes(&acc.bbox, &bins[split].bbox);
+            acc.count += bins[split].count;
+
+            if acc.count == 0 || right_count[split + 1] == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST + INTERSECT_COST * inv_parent_area
+                * (acc.count as f64 * acc.bbox.surface_area() + right_count[split + 1] as f64 * right_area[split + 1]);
+
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        let leaf_cost = INTERSECT_COST * prims.len() as f64;
+
+        if best_cost.is_infinite() {
+            return if prims.len() > MAX_LEAF_SIZE {
+                Some(Self::median_split(prims, axis))
+            } else {
+                None
+            };
+        }
+
+        if prims.len() <= MAX_LEAF_SIZE && best_cost >= leaf_cost {
+            return None;
+        }
+
+        // Partition in place: everything in bins up to best_split goes to the left
+        let mut mid = 0;
+        for i in 0..prims.len() {
+            if bin_of(&prims[i].centroid) <= best_split {
+                prims.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        Some(mid)
+    }
+
+    fn median_split(prims: &mut [PrimitiveInfo], axis: Axis) -> usize {
+        let mid = prims.len() / 2;
+        prims.select_nth_unstable_by(mid, |a, b| {
+            a.centroid.get_axis(axis).partial_cmp(&b.centroid.get_axis(axis)).unwrap_or(Ordering::Equal)
+        });
+        mid
+    }
+
+    fn compute_sah_cost(&self) -> f64 {
+        let root_area = match self.nodes.first() {
+            Some(root) if root.bbox.surface_area() > 0.0 => root.bbox.surface_area(),
+            _ => return 0.0,
+        };
+
+        self.nodes.iter().map(|node| {
+            let node_cost = if node.count > 0 { INTERSECT_COST * node.count as f64 } else { TRAVERSAL_COST };
+            node.bbox.surface_area() / root_area * node_cost
+        }).sum()
+    }
+}
+
+impl Hittable for FlatBvh {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest_so_far = ray_t.max;
+        let mut hit_anything = None;
+
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut node_index = 0;
+
+        loop {
+            let node = &self.nodes[node_index];
+            let mut box_t = Interval::new(ray_t.min, closest_so_far);
+
+            if node.bbox.hit(ray, &mut box_t) {
+                if node.count > 0 {
+                    for object in &self.objects[node.offset..node.offset + node.count] {
+                        if let Some(hit) = object.hit(ray, Interval::new(ray_t.min, closest_so_far)) {
+                            closest_so_far = hit.t;
+                            hit_anything = Some(hit);
+                        }
+                    }
+                } else {
+                    // Visit the near child first so its hits can cull the far one
+                    if ray.direction.get_axis(node.axis) < 0.0 {
+                        stack[stack_size] = node_index + 1;
+                        node_index = node.offset;
+                    } else {
+                        stack[stack_size] = node.offset;
+                        node_index += 1;
+                    }
+                    stack_size += 1;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            node_index = stack[stack_size];
+        }
+
+        hit_anything
+    }
+
+    // Every object along the ray contributes, so there is no closest hit to cull with, but the
+    // traversal stops as soon as something blocks the light completely
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        if self.nodes.is_empty() {
+            return 1.0;
+        }
+
+        let mut transmittance = 1.0;
+
+        let mut stack = [0usize; MAX_DEPTH];
+        let mut stack_size = 0;
+        let mut node_index = 0;
+
+        loop {
+            let node = &self.nodes[node_index];
+            let mut box_t = ray_t;
+
+            if node.bbox.hit(ray, &mut box_t) {
+                if node.count > 0 {
+                    for object in &self.objects[node.offset..node.offset + node.count] {
+                        transmittance *= object.transmittance(ray, ray_t);
+                        if transmittance <= 0.0 {
+                            return 0.0;
+                        }
+                    }
+                } else {
+                    stack[stack_size] = node.offset;
+                    stack_size += 1;
+                    node_index += 1;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            node_index = stack[stack_size];
+        }
+
+        transmittance
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.nodes.first().map_or(AABB::EMPTY, |root| root.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{random_unit_vector, Lambertian, Material};
+    use crate::rng::{self, gen_range};
+    use crate::sphere::Sphere;
+    use std::sync::Arc;
+
+    const SPHERE_COUNT: usize = 300;
+
+    // The same random spheres on every call
+    fn spheres() -> HittableList {
+        rng::seed(7);
+        let material: Arc<dyn Material + Send> = Arc::new(Lambertian::new(Vec3::splat(0.5)));
+
+        let mut list = HittableList { objects: vec![] };
+        for _ in 0..SPHERE_COUNT {
+            let center = Vec3::new(gen_range(-10.0..10.0), gen_range(-10.0..10.0), gen_range(-10.0..10.0));
+            list.add(Sphere::new(center, gen_range(0.05..1.0), Arc::clone(&material)));
+        }
+        list
+    }
+
+    #[test]
+    fn matches_linear_search() {
+        let linear = spheres();
+        let flat = FlatBvh::new(spheres());
+        let tree = BvhNode::new(spheres());
+
+        rng::seed(11);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Vec3::new(gen_range(-15.0..15.0), gen_range(-15.0..15.0), gen_range(-15.0..15.0));
+            let ray = Ray::new(origin, random_unit_vector(), 0.0);
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+
+            let expected = linear.hit(&ray, ray_t).map(|hit| hit.t);
+            assert_eq!(flat.hit(&ray, ray_t).map(|hit| hit.t), expected);
+            assert_eq!(tree.hit(&ray, ray_t).map(|hit| hit.t), expected);
+            assert_eq!(flat.transmittance(&ray, ray_t), linear.transmittance(&ray, ray_t));
+
+            hits += expected.is_some() as usize;
+        }
+
+        // Enough rays hit and miss for the comparison to mean something
+        assert!(hits > 200 && hits < 1800, "{hits} hits");
+    }
+
+    #[test]
+    fn reports_consistent_stats() {
+        let bvh = FlatBvh::new(spheres());
+        let stats = bvh.stats();
+
+        assert_eq!(stats.primitive_count, SPHERE_COUNT);
+        assert_eq!(stats.node_count, bvh.nodes.len());
+        // Every interior node has two children
+        assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+        assert_eq!(bvh.nodes.iter().filter(|node| node.count > 0).count(), stats.leaf_count);
+        assert!(stats.max_depth > 0 && stats.max_depth < MAX_DEPTH);
+
+        // Leaves cover every primitive exactly once
+        let mut covered = vec![false; SPHERE_COUNT];
+        for node in bvh.nodes.iter().filter(|node| node.count > 0) {
+            assert!(node.count <= MAX_LEAF_SIZE);
+            for seen in &mut covered[node.offset..node.offset + node.count] {
+                assert!(!*seen);
+                *seen = true;
+            }
+        }
+        assert!(covered.iter().all(|&seen| seen));
+
+        // A sensible tree beats testing every primitive
+        assert!(stats.sah_cost > 0.0 && stats.sah_cost < INTERSECT_COST * SPHERE_COUNT as f64 / 4.0, "{}", stats.sah_cost);
+    }
+
+    #[test]
+    fn empty_tree_hits_nothing() {
+        let bvh = FlatBvh::new(HittableList { objects: vec![] });
+        assert_eq!(bvh.stats().node_count, 0);
+        assert!(bvh.hit(&Ray::new(Vec3::ZERO, Vec3::new(1.0, 0.0, 0.0), 0.0), Interval::new(0.001, f64::INFINITY)).is_none());
+    }
+}
@@ -1,16 +1,25 @@
-use crate::hittable::{Hittable, HittableList};
+use crate::background::Background;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::image::Image;
 use crate::interval::Interval;
+use crate::light::Light;
+use crate::material::ScatterRecord;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
 use crate::ray::Ray;
+use crate::rng::{self, gen_range, random};
+use crate::sky::Sun;
 use crate::vec3::{Color, Vec3};
-use rand::{random, Rng};
-#[cfg(not(feature = "singlethread"))]
 use rayon::prelude::*;
 
+// Bounces every path makes before Russian roulette may end it
+const ROULETTE_DEPTH: i32 = 3;
+
 #[derive(Default)]
 pub struct Camera {
     pub aspect_ratio: f64,
     pub width: i32,
     pub samples_per_pixel: i32,
+    // Most bounces a path can make. Only a safety net, Russian roulette ends paths well before.
     pub max_depth: i32,
     pub vfov: f64,
     pub lookfrom: Vec3,
@@ -18,6 +27,20 @@ pub struct Camera {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_distance: f64,
+    // Poses to move between while the shutter is open, picked by the time of each ray, for camera
+    // shake or a moving viewpoint. The whole camera moves with them, as set up by lookfrom and
+    // lookat.
+    pub keyframes: Vec<CameraKeyframe>,
+    pub background: Background,
+    // Also record view space depth (Z) and world space normal (N.X, N.Y, N.Z) of the first surface
+    // seen through each pixel center as extra image channels
+    pub aov_channels: bool,
+    // Render scanlines one after another on the calling thread, reporting progress on stderr.
+    // Always on when built with the singlethread feature.
+    pub single_thread: bool,
+    // When set every scanline draws from its own generator seeded from this, so a render is
+    // reproducible regardless of how scanlines are scheduled across threads
+    pub seed: Option<u64>,
 
     height: i32,
     center: Vec3,
@@ -35,13 +58,32 @@ pub struct Camera {
 
 }
 
+// Where the camera is and looks at a point in the shutter interval, from 0 when it opens to 1 when
+// it closes
+#[derive(Debug, Copy, Clone)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+}
+
+impl CameraKeyframe {
+    fn lerp(&self, other: &CameraKeyframe, t: f64) -> Self {
+        Self {
+            time: (1.0 - t) * self.time + t * other.time,
+            lookfrom: (1.0 - t) * self.lookfrom + t * other.lookfrom,
+            lookat: (1.0 - t) * self.lookat + t * other.lookat,
+        }
+    }
+}
+
 impl Camera {
     pub fn new() -> Self {
         Self {
             aspect_ratio: 1.0,
             width: 100,
             samples_per_pixel: 10,
-            max_depth: 10,
+            max_depth: 64,
             vfov: 90.0,
             lookfrom: Vec3::ZERO,
             lookat: Vec3::new(0., 0., -1.),
@@ -52,67 +94,108 @@ impl Camera {
         }
     }
 
-    #[cfg(not(feature = "singlethread"))]
-    fn render_band(&self, band: &mut [Vec3], world: &HittableList, y: i32) {
+    fn render_band(&self, band: &mut [Vec3], world: &(dyn Hittable + Sync), lights: &HittableList, punctual_lights: &[Light], y: i32) {
+        if let Some(seed) = self.seed {
+            rng::seed(rng::mix_seed(seed, y as u64));
+        }
+
         for x in 0..self.width {
             let mut pixel_color = Color::ZERO;
 
             for _sample in 0..self.samples_per_pixel {
                 let ray = self.get_ray(x, y);
-                pixel_color += self.ray_color(&ray, self.max_depth, world);
+                pixel_color += self.ray_color(&ray, world, lights, punctual_lights);
             }
 
             band[x as usize] = self.pixel_samples_scale * pixel_color;
         }
     }
 
-    pub fn render(&mut self, world: &HittableList) {
-        self.initialize();
+    // Lights are the emissive objects of world that can be sampled directly, which cuts down noise
+    // from small or distant ones. They are still lit as usual when nothing samples them.
+    // Punctual lights have no surface in world and light it through shadow rays only.
+    pub fn render(&mut self, world: &(dyn Hittable + Sync), lights: &HittableList, punctual_lights: &[Light]) -> Result<Image, String> {
+        self.initialize()?;
 
-        // Render
-
-        #[cfg(not(feature = "singlethread"))] {
-            let mut pixels = vec![Vec3::ZERO; (self.width * self.height) as usize];
-            let bands: Vec<(usize, &mut [Vec3])> = pixels.chunks_mut(self.width as usize).enumerate().collect();
+        let mut image = Image::new(self.width as usize, self.height as usize);
+        let bands = image.pixels_mut().chunks_mut(self.width as usize).enumerate();
 
+        if self.single_thread || cfg!(feature = "singlethread") {
+            for (y, band) in bands {
+                if y % 10 == 0 {
+                    eprintln!("\rScanlines Remaining: {}", (self.height as usize - y));
+                }
 
-            println!("P3\n{} {}\n255", self.width, self.height);
+                self.render_band(band, world, lights, punctual_lights, y as i32);
+            }
 
+            eprintln!("\rDone!");
+        } else {
+            let bands: Vec<(usize, &mut [Vec3])> = bands.collect();
 
-            bands.into_par_iter().for_each(|(i, band)| {
-                self.render_band(band, world, i as i32);
+            bands.into_par_iter().for_each(|(y, band)| {
+                self.render_band(band, world, lights, punctual_lights, y as i32);
             });
-
-            for pixel in pixels {
-                write_color(&pixel);
-            }
         }
 
+        if self.aov_channels {
+            self.render_aovs(&mut image, world);
+        }
 
-        #[cfg(feature = "singlethread")] {
-            println!("P3\n{} {}\n255", self.width, self.height);
-
-            for y in 0..self.height {
-                if y % 10 == 0 {
-                    eprintln!("\rScanlines Remaining: {}", (self.height - y));
-                }
-                for x in 0..self.width {
-                    let mut pixel_color = Color::ZERO;
+        Ok(image)
+    }
 
-                    for _sample in 0..self.samples_per_pixel {
-                        let ray = self.get_ray(x, y);
-                        pixel_color += self.ray_color(&ray, self.max_depth, world);
+    fn render_aovs(&self, image: &mut Image, world: &(dyn Hittable + Sync)) {
+        let pixel_count = (self.width * self.height) as usize;
+        let mut depth = Vec::with_capacity(pixel_count);
+        let mut normals = [Vec::with_capacity(pixel_count), Vec::with_capacity(pixel_count), Vec::with_capacity(pixel_count)];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel_center = self.pixel00_loc + (x as f64 * self.pixel_delta_u) + (y as f64 * self.pixel_delta_v);
+                let direction = pixel_center - self.center;
+                let ray = self.posed(Ray::new(self.center, direction, 0.0));
+
+                match world.hit(&ray, Interval::new(0.001, f64::INFINITY)) {
+                    Some(hit) => {
+                        // Distance along the view axis rather than along the ray, as compositors
+                        // expect. Posing turns the ray and the axis together, so it holds for both.
+                        depth.push((hit.t * direction.dot(&-self.w)) as f32);
+                        normals[0].push(hit.normal.x as f32);
+                        normals[1].push(hit.normal.y as f32);
+                        normals[2].push(hit.normal.z as f32);
+                    }
+                    None => {
+                        depth.push(f32::INFINITY);
+                        normals.iter_mut().for_each(|n| n.push(0.0));
                     }
-
-                    write_color(&(self.pixel_samples_scale * pixel_color));
                 }
             }
-
-            eprintln!("\rDone!");
         }
+
+        let [nx, ny, nz] = normals;
+        image.add_channel("Z", depth);
+        image.add_channel("N.X", nx);
+        image.add_channel("N.Y", ny);
+        image.add_channel("N.Z", nz);
     }
 
-    fn initialize(&mut self) {
+    fn initialize(&mut self) -> Result<(), String> {
+        if self.width < 1 {
+            return Err(format!("image width must be at least 1, got {}", self.width));
+        }
+        if self.samples_per_pixel < 1 {
+            return Err(format!("samples per pixel must be at least 1, got {}", self.samples_per_pixel));
+        }
+        if self.max_depth < 0 {
+            return Err(format!("max depth must not be negative, got {}", self.max_depth));
+        }
+        if self.aspect_ratio <= 0.0 || self.aspect_ratio.is_nan() {
+            return Err(format!("aspect ratio must be positive, got {}", self.aspect_ratio));
+        }
+
+        self.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
         let height = (self.width as f64 / self.aspect_ratio) as i32;
         self.height = if height < 1 { 1 } else { height };
 
@@ -141,7 +224,7 @@ impl Camera {
         // calculate horizontal and vertical delta between pixels
 
         self.pixel_delta_u = viewport_u / self.width as f64;
-        self.pixel_delta_v = viewport_v / height as f64;
+        self.pixel_delta_v = viewport_v / self.height as f64;
 
         // find location of upper left pixel
 
@@ -153,26 +236,128 @@ impl Camera {
         let defocus_radius = self.focus_distance * (self.defocus_angle / 2.0).to_radians().tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
+
+        Ok(())
     }
 
-    fn ray_color(&self, ray: &Ray, depth: i32, world: &HittableList) -> Color {
-        if depth <= 0 {
+    fn ray_color(&self, ray: &Ray, world: &(dyn Hittable + Sync), lights: &HittableList, punctual_lights: &[Light]) -> Color {
+        let mut ray = *ray;
+        let mut color = Color::ZERO;
+        // Fraction of the light arriving along ray that makes it back to the camera
+        let mut throughput = Color::ONE;
+        // Density the previous bounce chose the ray's direction with, if it also sampled the
+        // lights from there
+        let mut scattering_pdf = None;
+
+        for depth in 0..self.max_depth {
+            // Light reached by both strategies is shared between them (multiple importance
+            // sampling). Only worked out for light that was found, most bounces find none.
+            let weighted = |light: Color| {
+                let weight = match scattering_pdf {
+                    Some(scattering_pdf) if light.length_squared() > 0.0 => {
+                        let light_pdf = light_pdf(lights, self.background.sun(), ray.origin).map_or(0.0, |pdf| pdf.value(ray.direction));
+                        power_heuristic(scattering_pdf, light_pdf)
+                    }
+                    _ => 1.0,
+                };
+                weight * throughput * light
+            };
+
+            let Some(hit) = world.hit(&ray, Interval::new(0.001, f64::INFINITY)) else {
+                color += weighted(self.background.color(&ray));
+                break;
+            };
+
+            color += weighted(hit.material.emitted(&hit));
+
+            match hit.material.scatter(&ray, &hit) {
+                None => break,
+                Some(ScatterRecord::Specular { ray: scattered, attenuation }) => {
+                    throughput = throughput * attenuation;
+                    scattering_pdf = None;
+                    ray = scattered;
+                }
+                Some(ScatterRecord::Pdf(pdf)) => {
+                    let light_pdf = light_pdf(lights, self.background.sun(), hit.point);
+                    if let Some(light_pdf) = &light_pdf {
+                        color += throughput * self.sample_lights(&ray, &hit, pdf.as_ref(), world, light_pdf.as_ref());
+                    }
+                    for light in punctual_lights {
+                        color += throughput * self.sample_punctual(&ray, &hit, light, world);
+                    }
+
+                    let direction = pdf.generate();
+                    let pdf_value = pdf.value(direction);
+                    let scattering = hit.material.eval(&ray, &hit, direction);
+
+                    // Directions the material doesn't scatter into carry no light
+                    if pdf_value <= 0.0 || scattering.length_squared() == 0.0 {
+                        break;
+                    }
+
+                    throughput = throughput * scattering / pdf_value;
+                    scattering_pdf = light_pdf.is_some().then_some(pdf_value);
+                    ray = Ray::new(hit.point, direction, ray.time);
+                }
+            }
+
+            // Russian roulette: paths carrying little light are ended at random, and the ones that
+            // go on make up for them, which stops dim paths without darkening the image
+            if depth + 1 >= ROULETTE_DEPTH {
+                let survival = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+                if random::<f64>() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+        }
+
+        color
+    }
+
+    // Light arriving at the hit from a direction towards the lights, weighted against the chance of
+    // the material scattering that way
+    fn sample_lights(&self, ray: &Ray, hit: &HitRecord, pdf: &dyn Pdf, world: &(dyn Hittable + Sync), light_pdf: &dyn Pdf) -> Color {
+        let direction = light_pdf.generate();
+        let pdf_value = light_pdf.value(direction);
+        if pdf_value <= 0.0 {
             return Color::ZERO;
         }
 
-        if let Some(hit) = world.hit(ray, Interval::new(0.001, f64::INFINITY)) {
-            return if let Some((scattered, attenuation)) = hit.material.scatter(ray, &hit) {
-                attenuation * self.ray_color(&scattered, depth - 1, world)
-            } else {
-                Color::ZERO
-            };
+        let scattering = hit.material.eval(ray, hit, direction);
+        if scattering.length_squared() == 0.0 {
+            return Color::ZERO;
         }
 
-        // sky color
+        // Whatever the shadow ray reaches first is what is seen that way, occluders emit nothing
+        let shadow = Ray::new(hit.point, direction, ray.time);
+        let incoming = match world.hit(&shadow, Interval::new(0.001, f64::INFINITY)) {
+            Some(occluder) => occluder.material.emitted(&occluder),
+            None => self.background.color(&shadow),
+        };
 
-        let unit_direction = ray.direction.unit_vector();
-        let a = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        power_heuristic(pdf_value, pdf.value(direction)) * scattering * incoming / pdf_value
+    }
+
+    // Light from a punctual light, less what is blocked or absorbed on the way. Scattered rays never
+    // find these lights so there is nothing to weight against.
+    fn sample_punctual(&self, ray: &Ray, hit: &HitRecord, light: &Light, world: &(dyn Hittable + Sync)) -> Color {
+        let Some(sample) = light.sample(hit.point) else {
+            return Color::ZERO;
+        };
+
+        let scattering = hit.material.eval(ray, hit, sample.direction);
+        if scattering.length_squared() == 0.0 {
+            return Color::ZERO;
+        }
+
+        let shadow = Ray::new(hit.point, sample.direction, ray.time);
+        let transmittance = world.transmittance(&shadow, Interval::new(0.001, sample.distance));
+        if transmittance <= 0.0 {
+            return Color::ZERO;
+        }
+
+        transmittance * scattering * sample.irradiance
     }
 
     fn get_ray(&self, x: i32, y: i32) -> Ray {
@@ -187,7 +372,33 @@ impl Camera {
         let ray_direction = pixel_sample - ray_origin;
         let ray_time = random();
 
-        Ray::new(ray_origin, ray_direction, ray_time)
+        self.posed(Ray::new(ray_origin, ray_direction, ray_time))
+    }
+
+    // Moves a ray of the camera set up by initialize to where the keyframes put the camera at the
+    // ray's time. Poses before the first or after the last keyframe are held.
+    fn posed(&self, ray: Ray) -> Ray {
+        let next = self.keyframes.partition_point(|key| key.time <= ray.time);
+        let pose = match next {
+            0 => match self.keyframes.first() {
+                Some(first) => *first,
+                None => return ray,
+            },
+            _ if next == self.keyframes.len() => self.keyframes[next - 1],
+            _ => {
+                let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+                a.lerp(b, (ray.time - a.time) / (b.time - a.time))
+            }
+        };
+
+        let w = (pose.lookfrom - pose.lookat).unit_vector();
+        let u = self.vup.cross(&w).unit_vector();
+        let v = w.cross(&u);
+
+        // Same coordinates in the posed camera's frame
+        let rotate = |d: Vec3| d.dot(&self.u) * u + d.dot(&self.v) * v + d.dot(&self.w) * w;
+
+        Ray::new(pose.lookfrom + rotate(ray.origin - self.center), rotate(ray.direction), ray.time)
     }
 
     fn defocus_disk_sample(&self) -> Vec3 {
@@ -197,47 +408,105 @@ impl Camera {
 
     fn sample_square(&self) -> Vec3 {
         // Returns the vector to a random point in the [-0.5, -0.5] - [0.5, 0.5] unit square
-        let mut rng = rand::thread_rng();
-
-        Vec3::new(rng.gen_range(0.0..=1.0) - 0.5,
-                  rng.gen_range(0.0..=1.0) - 0.5,
+        Vec3::new(gen_range(0.0..=1.0) - 0.5,
+                  gen_range(0.0..=1.0) - 0.5,
                   0.)
     }
 }
 
+// Directions from origin towards the lights, and the sun when the background has one. Each is
+// picked half the time when there are both, and None when there is neither.
+fn light_pdf<'a>(lights: &'a HittableList, sun: Option<&'a Sun>, origin: Vec3) -> Option<Box<dyn Pdf + 'a>> {
+    let objects = (!lights.objects.is_empty()).then(|| HittablePdf::new(lights, origin));
+
+    match (objects, sun) {
+        (Some(objects), Some(sun)) => Some(Box::new(MixturePdf::new(Box::new(objects), Box::new(sun)))),
+        (Some(objects), None) => Some(Box::new(objects)),
+        (None, Some(sun)) => Some(Box::new(sun)),
+        (None, None) => None,
+    }
+}
+
+// Weight of a sample drawn with density f when g could have produced it as well (Veach 1997)
+fn power_heuristic(f: f64, g: f64) -> f64 {
+    let (f2, g2) = (f * f, g * g);
+    if f2 + g2 == 0.0 { 0.0 } else { f2 / (f2 + g2) }
+}
+
 pub fn random_in_unit_disk() -> Vec3 {
     loop {
-        let point = Vec3::new(random(), random(), 0.0);
+        let point = Vec3::new(gen_range(-1.0..1.0), gen_range(-1.0..1.0), 0.0);
         if point.length_squared() < 1.0 {
             return point;
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::quad::Quad;
+    use std::sync::Arc;
+
+    fn render_error(configure: impl FnOnce(&mut Camera)) -> String {
+        let mut camera = Camera::new();
+        configure(&mut camera);
+
+        let empty = HittableList { objects: vec![] };
+        match camera.render(&empty, &empty, &[]) {
+            Ok(_) => panic!("expected the camera to be rejected"),
+            Err(err) => err,
+        }
+    }
 
-fn write_color(color: &Color) {
-    let r = color.x;
-    let g = color.y;
-    let b = color.z;
-
-    // Apply linear to gamma transform for gama 2
-    let r = linear_to_gamma(r);
-    let g = linear_to_gamma(g);
-    let b = linear_to_gamma(b);
-
-    // Translate the [0, 1] component values to the range [0, 255]
-    let intensity = Interval::new(0., 0.999);
-    let r = (256. * intensity.clamp(r)) as u32;
-    let g = (256. * intensity.clamp(g)) as u32;
-    let b = (256. * intensity.clamp(b)) as u32;
+    #[test]
+    fn rejects_invalid_settings() {
+        assert_eq!(render_error(|camera| camera.width = 0), "image width must be at least 1, got 0");
+        assert_eq!(render_error(|camera| camera.samples_per_pixel = 0), "samples per pixel must be at least 1, got 0");
+        assert_eq!(render_error(|camera| camera.max_depth = -1), "max depth must not be negative, got -1");
+        assert_eq!(render_error(|camera| camera.aspect_ratio = f64::NAN), "aspect ratio must be positive, got NaN");
+    }
 
-    println!("{r} {g} {b}");
-}
+    #[test]
+    fn keyframes_move_the_whole_camera() {
+        let mut camera = Camera::new();
+        camera.keyframes = vec![
+            CameraKeyframe { time: 1.0, lookfrom: Vec3::new(2.0, 0.0, 0.0), lookat: Vec3::new(1.0, 0.0, 0.0) },
+            CameraKeyframe { time: 0.0, lookfrom: Vec3::ZERO, lookat: Vec3::new(0.0, 0.0, -1.0) },
+        ];
+        camera.initialize().unwrap();
+
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-12;
+        let posed = |time: f64| camera.posed(Ray::new(Vec3::new(0.0, 0.1, 0.0), Vec3::new(0.0, 0.0, -1.0), time));
+
+        let start = posed(0.0);
+        assert!(close(start.origin, Vec3::new(0.0, 0.1, 0.0)) && close(start.direction, Vec3::new(0.0, 0.0, -1.0)));
+
+        // Halfway the camera has moved one unit along x and turned 45 degrees towards -x
+        let middle = posed(0.5);
+        assert!(close(middle.origin, Vec3::new(1.0, 0.1, 0.0)));
+        assert!(close(middle.direction, Vec3::new(-1.0, 0.0, -1.0) / 2.0f64.sqrt()));
+
+        // The last pose is held after its keyframe
+        let end = posed(2.0);
+        assert!(close(end.origin, Vec3::new(2.0, 0.1, 0.0)) && close(end.direction, Vec3::new(-1.0, 0.0, 0.0)));
+    }
 
-#[inline]
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    if linear_component > 0.0 {
-        linear_component.sqrt()
-    } else {
-        0.0
+    #[test]
+    fn depth_channel_is_distance_along_the_view_axis() {
+        let mut camera = Camera::new();
+        camera.width = 8;
+        camera.samples_per_pixel = 1;
+        camera.max_depth = 0;
+        camera.aov_channels = true;
+
+        // Wall facing the camera 5 units away, filling the whole view
+        let mut world = HittableList { objects: vec![] };
+        let material: Arc<dyn Material + Send> = Arc::new(Lambertian::new(Color::splat(0.5)));
+        world.add(Quad::new(Vec3::new(-20.0, -20.0, -5.0), Vec3::new(40.0, 0.0, 0.0), Vec3::new(0.0, 40.0, 0.0), material));
+
+        let image = camera.render(&world, &HittableList { objects: vec![] }, &[]).unwrap();
+        let depth = image.channels().iter().find(|channel| channel.name == "Z").unwrap();
+        assert!(depth.values.iter().all(|&z| (z - 5.0).abs() < 1e-5), "{:?}", depth.values);
     }
-}
\ No newline at end of file
+}
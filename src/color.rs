@@ -0,0 +1,52 @@
+use crate::vec3::Color;
+
+// Linear sRGB color of CIE XYZ tristimulus values, with colors outside the sRGB gamut clipped
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+        (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+    )
+}
+
+// Linear sRGB color of chromaticity x, y with the given luminance
+pub fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::ZERO;
+    }
+
+    xyz_to_rgb(x / y * luminance, luminance, (1.0 - x - y) / y * luminance)
+}
+
+// Linear sRGB color of a blackbody at temperature in kelvin, with luminance 1. Planck's law is
+// integrated against an analytic fit of the CIE 1931 color matching functions (Wyman, Sloan and
+// Shirley 2013). Colors outside the sRGB gamut are clipped, and temperatures too low to glow
+// visibly give black.
+pub fn blackbody(kelvin: f64) -> Color {
+    if kelvin <= 0.0 {
+        return Color::ZERO;
+    }
+
+    // Piecewise Gaussian with different widths left and right of the peak
+    let lobe = |x: f64, mean: f64, left: f64, right: f64| {
+        let width = if x < mean { left } else { right };
+        (-0.5 * ((x - mean) / width).powi(2)).exp()
+    };
+
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    for step in 0..=80 {
+        let nm = 380.0 + 5.0 * step as f64;
+        // Planck's law up to a constant factor, with the second radiation constant in nm K
+        let radiance = 1.0 / (nm.powi(5) * ((1.438_777e7 / (nm * kelvin)).exp() - 1.0));
+
+        x += radiance * (1.056 * lobe(nm, 599.8, 37.9, 31.0) + 0.362 * lobe(nm, 442.0, 16.0, 26.7) - 0.065 * lobe(nm, 501.1, 20.4, 26.2));
+        y += radiance * (0.821 * lobe(nm, 568.8, 46.9, 40.5) + 0.286 * lobe(nm, 530.9, 16.3, 31.1));
+        z += radiance * (1.217 * lobe(nm, 437.0, 11.8, 36.0) + 0.681 * lobe(nm, 459.0, 26.0, 13.8));
+    }
+
+    if y <= 0.0 {
+        return Color::ZERO;
+    }
+
+    xyz_to_rgb(x / y, 1.0, z / y)
+}
@@ -0,0 +1,366 @@
+use crate::bvh::FlatBvh;
+use crate::camera::Camera;
+use crate::hittable::{Hittable, HittableList};
+use crate::image::Image;
+use crate::light::Light;
+use crate::material::{Material, Principled};
+use crate::matrix::Mat4;
+use crate::mesh::{MeshData, TriangleMesh};
+use crate::scene::{Scene, SceneError};
+use crate::texture::{SolidColor, Texture};
+use crate::transform::Transform;
+use crate::vec3::{Color, Vec3};
+use ::gltf::camera::Projection;
+use ::gltf::image::Format;
+use ::gltf::khr_lights_punctual::Kind;
+use ::gltf::mesh::Mode;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// Loads a glTF 2.0 file (.gltf or .glb) as a scene. Every mesh is built once and instanced by the
+// nodes using it, and the first perspective camera becomes the scene camera. Without one the
+// camera looks at the whole scene from +z.
+pub fn load_gltf(path: &Path) -> Result<Scene, SceneError> {
+    let error = |message: String| SceneError {
+        path: Some(path.to_path_buf()),
+        location: None,
+        message,
+    };
+
+    let (document, buffers, images) = ::gltf::import(path).map_err(|err| error(err.to_string()))?;
+
+    let mut importer = Importer {
+        buffers: &buffers,
+        images: &images,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        world: HittableList { objects: vec![] },
+        lights: vec![],
+        camera: None,
+    };
+
+    let scene = document.default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| error("file contains no scene".to_string()))?;
+
+    for node in scene.nodes() {
+        importer.node(&node, Mat4::IDENTITY).map_err(error)?;
+    }
+
+    let camera = match importer.camera.take() {
+        Some(camera) => camera,
+        None => frame(&importer.world),
+    };
+
+    // Emissive meshes are only lit by rays that happen to hit them
+    Ok(Scene {
+        world: importer.world,
+        lights: HittableList { objects: vec![] },
+        punctual_lights: importer.lights,
+        camera,
+    })
+}
+
+struct Importer<'a> {
+    buffers: &'a [::gltf::buffer::Data],
+    images: &'a [::gltf::image::Data],
+    // Converted images by glTF index and the channel read for scalar data, and materials by index
+    textures: HashMap<(usize, Option<usize>), Arc<Image>>,
+    materials: HashMap<Option<usize>, Arc<dyn Material + Send>>,
+    // Meshes in their own space by glTF index, None if they have no triangles
+    meshes: HashMap<usize, Option<Arc<dyn Hittable + Send + Sync>>>,
+    world: HittableList,
+    lights: Vec<Light>,
+    camera: Option<Camera>,
+}
+
+impl Importer<'_> {
+    fn node(&mut self, node: &::gltf::Node, parent: Mat4) -> Result<(), String> {
+        let transform = parent * Mat4::from_columns(node.transform().matrix().map(|c| c.map(|v| v as f64)));
+
+        if let Some(mesh) = node.mesh() {
+            // Scaling a node to zero is a common way of hiding it, and there'd be nothing to see
+            // of its mesh anyway, so it is left out rather than failing the import
+            if let Some(object) = self.mesh(&mesh)? {
+                if let Some(object) = Transform::new(object, transform) {
+                    self.world.add(object);
+                }
+            }
+        }
+
+        if let Some(light) = node.light().and_then(|light| convert_light(&light, transform)) {
+            self.lights.push(light);
+        }
+
+        if let Some(camera) = node.camera() {
+            if let (None, Projection::Perspective(perspective)) = (&self.camera, camera.projection()) {
+                // glTF cameras look down their local -z axis with +y up
+                let mut camera = Camera::new();
+                camera.lookfrom = transform.transform_point(Vec3::ZERO);
+                camera.lookat = camera.lookfrom + transform.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+                camera.vup = transform.transform_vector(Vec3::new(0.0, 1.0, 0.0));
+                camera.vfov = (perspective.yfov() as f64).to_degrees();
+                if let Some(aspect_ratio) = perspective.aspect_ratio() {
+                    camera.aspect_ratio = aspect_ratio as f64;
+                }
+                self.camera = Some(camera);
+            }
+        }
+
+        for child in node.children() {
+            self.node(&child, transform)?;
+        }
+
+        Ok(())
+    }
+
+    fn mesh(&mut self, mesh: &::gltf::Mesh) -> Result<Option<Arc<dyn Hittable + Send + Sync>>, String> {
+        if let Some(object) = self.meshes.get(&mesh.index()) {
+            return Ok(object.clone());
+        }
+
+        let mut primitives = HittableList { objects: vec![] };
+        for primitive in mesh.primitives() {
+            if let Some(data) = self.primitive(&primitive)? {
+                primitives.add(TriangleMesh::new(Arc::new(data)));
+            }
+        }
+
+        let object: Option<Arc<dyn Hittable + Send + Sync>> = match primitives.objects.len() {
+            0 => None,
+            1 => Some(Arc::new(primitives)),
+            _ => Some(Arc::new(FlatBvh::new(primitives))),
+        };
+
+        self.meshes.insert(mesh.index(), object.clone());
+        Ok(object)
+    }
+
+    fn primitive(&mut self, primitive: &::gltf::Primitive) -> Result<Option<MeshData>, String> {
+        // Points and lines have no surface to hit
+        if !matches!(primitive.mode(), Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan) {
+            return Ok(None);
+        }
+
+        let reader = primitive.reader(|buffer| self.buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+        let Some(positions) = reader.read_positions() else {
+            return Ok(None);
+        };
+        let positions: Vec<Vec3> = positions.map(to_vec3).collect();
+
+        let normals: Vec<Vec3> = reader.read_normals()
+            .map(|normals| normals.map(|n| to_vec3(n).unit_vector()).collect())
+            .unwrap_or_default();
+
+        let material = primitive.material();
+        let uv_set = material.pbr_metallic_roughness().base_color_texture().map_or(0, |info| info.tex_coord());
+        // glTF puts the texture origin at the top left, textures here expect v = 0 at the bottom
+        let uvs: Vec<[f64; 2]> = reader.read_tex_coords(uv_set)
+            .map(|uvs| uvs.into_f32().map(|[u, v]| [u as f64, 1.0 - v as f64]).collect())
+            .unwrap_or_default();
+
+        let colors: Vec<Color> = reader.read_colors(0)
+            .map(|colors| colors.into_rgb_f32().map(to_vec3).collect())
+            .unwrap_or_default();
+
+        let vertices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        let indices: Vec<[u32; 3]> = match primitive.mode() {
+            Mode::TriangleStrip => vertices.windows(3).enumerate()
+                .map(|(i, w)| if i % 2 == 0 { [w[0], w[1], w[2]] } else { [w[1], w[0], w[2]] })
+                .collect(),
+            Mode::TriangleFan => (2..vertices.len()).map(|i| [vertices[0], vertices[i - 1], vertices[i]]).collect(),
+            _ => vertices.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+        };
+
+        if indices.iter().flatten().any(|&i| i as usize >= positions.len()) {
+            return Err("primitive references a missing vertex".to_string());
+        }
+
+        let check = |len: usize, attribute: &str| {
+            if len != 0 && len != positions.len() {
+                return Err(format!("{attribute} count doesn't match the vertex count"));
+            }
+            Ok(())
+        };
+        check(normals.len(), "normal")?;
+        check(uvs.len(), "texture coordinate")?;
+        check(colors.len(), "color")?;
+
+        Ok(Some(MeshData {
+            material: self.material(&material),
+            positions,
+            normals,
+            uvs,
+            colors,
+            indices,
+        }))
+    }
+
+    // Metallic-roughness materials map directly onto the principled material
+    fn material(&mut self, material: &::gltf::Material) -> Arc<dyn Material + Send> {
+        if let Some(material) = self.materials.get(&material.index()) {
+            return Arc::clone(material);
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _alpha] = pbr.base_color_factor().map(|c| c as f64);
+
+        let base_color = self.repeating(pbr.base_color_texture().map(|info| info.texture()), None, Color::new(r, g, b));
+        let mut converted = Principled::from_texture(base_color);
+
+        // Roughness is in the green channel and metalness in the blue one
+        let metallic_roughness = pbr.metallic_roughness_texture().map(|info| info.texture());
+        converted.metallic = self.repeating(metallic_roughness.clone(), Some(2), Color::splat(pbr.metallic_factor() as f64));
+        converted.roughness = self.repeating(metallic_roughness, Some(1), Color::splat(pbr.roughness_factor() as f64));
+
+        let emission = to_vec3(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0) as f64;
+        converted.emission = self.repeating(material.emissive_texture().map(|info| info.texture()), None, emission);
+
+        if let Some(transmission) = material.transmission() {
+            let factor = Color::splat(transmission.transmission_factor() as f64);
+            converted.transmission = self.repeating(transmission.transmission_texture().map(|info| info.texture()), Some(0), factor);
+        }
+
+        // The index of refraction also sets the reflectance of opaque dielectrics
+        let ior = material.ior().unwrap_or(1.5) as f64;
+        let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+        converted.specular = Arc::new(SolidColor::new(Color::splat(f0 / 0.08)));
+        converted.refraction_index = ior;
+
+        let converted: Arc<dyn Material + Send> = Arc::new(converted);
+        self.materials.insert(material.index(), Arc::clone(&converted));
+        converted
+    }
+
+    // Texture repeating the image of a glTF texture, or a constant factor without one. Scalar data
+    // is read linearly from a single channel.
+    fn repeating(&mut self, texture: Option<::gltf::Texture>, channel: Option<usize>, factor: Color) -> Arc<dyn Texture> {
+        match texture {
+            Some(texture) => Arc::new(RepeatingTexture {
+                image: self.texture(texture.source().index(), channel),
+                factor,
+            }),
+            None => Arc::new(SolidColor::new(factor)),
+        }
+    }
+
+    fn texture(&mut self, index: usize, channel: Option<usize>) -> Arc<Image> {
+        let images = self.images;
+        Arc::clone(self.textures.entry((index, channel)).or_insert_with(|| Arc::new(convert_image(&images[index], channel))))
+    }
+}
+
+// Color textures are gamma encoded, converted with the same gamma 2 as 8 bit PNG images. A channel
+// is read linearly into every component instead.
+fn convert_image(data: &::gltf::image::Data, channel: Option<usize>) -> Image {
+    let (channels, bytes) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let linear = |pixel: &[u8], c: usize| {
+        let bytes = &pixel[c * bytes..(c + 1) * bytes];
+        match bytes.len() {
+            1 => bytes[0] as f64 / 255.0,
+            2 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 65535.0,
+            _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        }
+    };
+    let component = |pixel: &[u8], c: usize| {
+        let value = linear(pixel, c);
+        if bytes == 4 { value } else { value * value }
+    };
+
+    let pixels = data.pixels.chunks_exact(channels * bytes)
+        .map(|pixel| match (channel, channels) {
+            (Some(c), _) => Color::splat(linear(pixel, c.min(channels - 1))),
+            (None, 1 | 2) => Color::splat(component(pixel, 0)),
+            (None, _) => Color::new(component(pixel, 0), component(pixel, 1), component(pixel, 2)),
+        })
+        .collect();
+
+    Image::from_pixels(data.width as usize, data.height as usize, pixels)
+}
+
+// Image texture tinted by a constant factor, repeating outside [0, 1] as glTF samplers do by default
+struct RepeatingTexture {
+    image: Arc<Image>,
+    factor: Color,
+}
+
+impl Texture for RepeatingTexture {
+    fn value(&self, u: f64, v: f64, _point: &Vec3) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        if width == 0 || height == 0 {
+            return self.factor;
+        }
+
+        let x = ((u.rem_euclid(1.0) * width as f64) as usize).min(width - 1);
+        let y = (((1.0 - v.rem_euclid(1.0)) * height as f64) as usize).min(height - 1);
+
+        self.factor * self.image.get(x, y)
+    }
+}
+
+// Places a camera on the +z side of the world, far enough back to see all of it
+fn frame(world: &HittableList) -> Camera {
+    let mut camera = Camera::new();
+    camera.vfov = 40.0;
+
+    if world.objects.is_empty() {
+        return camera;
+    }
+
+    let bbox = world.bounding_box();
+    let radius = 0.5 * Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length();
+    let distance = radius / (0.5 * camera.vfov).to_radians().sin();
+
+    camera.lookat = bbox.centroid();
+    camera.lookfrom = camera.lookat + Vec3::new(0.0, 0.0, distance);
+    camera.focus_distance = distance;
+
+    camera
+}
+
+fn to_vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0] as f64, v[1] as f64, v[2] as f64)
+}
+
+// KHR_lights_punctual lights shine down their local -z axis. Their range is only a hint for
+// culling, and is ignored. Lights whose axis a zero scale collapses have no direction to shine in
+// and are left out, like meshes hidden that way.
+fn convert_light(light: &::gltf::khr_lights_punctual::Light, transform: Mat4) -> Option<Light> {
+    let intensity = light.intensity() as f64 * to_vec3(light.color());
+    let position = transform.transform_point(Vec3::ZERO);
+    let direction = transform.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+    if !matches!(light.kind(), Kind::Point) && direction.length_squared() == 0.0 {
+        return None;
+    }
+
+    Some(match light.kind() {
+        Kind::Directional => Light::directional(direction, intensity),
+        Kind::Point => Light::point(position, intensity),
+        Kind::Spot { inner_cone_angle, outer_cone_angle } => Light::spot(
+            position,
+            direction,
+            intensity,
+            (inner_cone_angle as f64).to_degrees(),
+            (outer_cone_angle as f64).to_degrees(),
+        ),
+    })
+}
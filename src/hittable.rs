@@ -1,7 +1,9 @@
+use crate::aabb::AABB;
 use crate::interval::Interval;
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vec3::Vec3;
+use crate::rng::gen_range;
+use crate::vec3::{Color, Vec3};
 use std::sync::Arc;
 
 pub struct HitRecord {
@@ -9,6 +11,11 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub material: Arc<dyn Material + Send>,
     pub t: f64,
+    // Surface coordinates of the hit point, used for texture lookups
+    pub u: f64,
+    pub v: f64,
+    // Interpolated vertex color of meshes that have one, multiplied into the material's albedo
+    pub color: Option<Color>,
     pub front_face: bool,
 }
 
@@ -28,16 +35,33 @@ impl HitRecord {
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord>;
+
+    fn bounding_box(&self) -> AABB;
+
+    // Density of random() picking direction from origin, per unit solid angle. Objects that can't
+    // be sampled, which is the default, give zero.
+    fn pdf_value(&self, _origin: Vec3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // Random direction from origin towards a point on the object
+    fn random(&self, _origin: Vec3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // Fraction of the light travelling along ray within ray_t that gets through the object. Surfaces
+    // block all of it, media override this to let part of it through.
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        if self.hit(ray, ray_t).is_some() { 0.0 } else { 1.0 }
+    }
 }
 
 pub struct HittableList {
-    pub objects: Vec<Box<dyn Hittable + Sync>>,
+    pub objects: Vec<Box<dyn Hittable + Send + Sync>>,
 }
 
-unsafe impl Send for HittableList {}
-
 impl HittableList {
-    pub fn add(&mut self, object: impl Hittable + 'static + Sync) {
+    pub fn add(&mut self, object: impl Hittable + 'static + Send + Sync) {
         self.objects.push(Box::new(object));
     }
 
@@ -60,5 +84,34 @@ impl Hittable for HittableList {
 
         hit_anything
     }
+
+    fn bounding_box(&self) -> AABB {
+        self.objects.iter().fold(AABB::EMPTY, |bbox, object| AABB::from_boxes(&bbox, &object.bounding_box()))
+    }
+
+    // Every object is picked with the same chance
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects.iter().map(|object| weight * object.pdf_value(origin, direction)).sum()
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+
+        self.objects[gen_range(0..self.objects.len())].random(origin)
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        let mut transmittance = 1.0;
+        for object in &self.objects {
+            transmittance *= object.transmittance(ray, ray_t);
+            if transmittance <= 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
 }
 
@@ -0,0 +1,683 @@
+use crate::interval::Interval;
+use crate::vec3::Color;
+use exr::prelude::{f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Layer, LayerAttributes, SmallVec, WritableImage};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::Path;
+
+// Linear radiance values for every pixel, stored row by row starting at the top left
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    channels: Vec<Channel>,
+}
+
+// Additional single valued per pixel data (depth, normals, ...) written alongside the color by
+// formats that support arbitrary channels
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::ZERO; width * height],
+            channels: vec![],
+        }
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "pixel count does not match image dimensions");
+
+        Self {
+            width,
+            height,
+            pixels,
+            channels: vec![],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    pub fn add_channel(&mut self, name: &str, values: Vec<f32>) {
+        assert_eq!(values.len(), self.width * self.height, "channel size does not match image dimensions");
+
+        self.channels.push(Channel { name: name.to_string(), values });
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // Bilinearly filtered lookup at continuous pixel coordinates, wrapping horizontally and
+    // clamping vertically (the layout of equirectangular maps)
+    pub fn sample_bilinear(&self, x: f64, y: f64) -> Color {
+        if self.pixels.is_empty() {
+            return Color::ZERO;
+        }
+
+        let x = x - 0.5;
+        let y = (y - 0.5).clamp(0.0, (self.height - 1) as f64);
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+
+        let x0 = (x0 as i64).rem_euclid(self.width as i64) as usize;
+        let x1 = (x0 + 1) % self.width;
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let top = (1.0 - tx) * self.get(x0, y0) + tx * self.get(x1, y0);
+        let bottom = (1.0 - tx) * self.get(x0, y1) + tx * self.get(x1, y1);
+
+        (1.0 - ty) * top + ty * bottom
+    }
+
+    // Applies the display transform (gamma 2, clamped to [0, 1)) and quantizes to 8 bit RGB triples
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        let intensity = Interval::new(0., 0.999);
+
+        self.pixels.iter().flat_map(|color| {
+            [color.x, color.y, color.z].map(|c| (256. * intensity.clamp(linear_to_gamma(c))) as u8)
+        }).collect()
+    }
+}
+
+pub trait ImageWriter {
+    fn write_to(&self, image: &Image, out: &mut dyn Write) -> io::Result<()>;
+
+    fn save(&self, image: &Image, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(image, &mut out)?;
+        out.flush()
+    }
+}
+
+// Binary (P6) portable pixmap
+pub struct PpmWriter;
+
+impl ImageWriter for PpmWriter {
+    fn write_to(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", image.width, image.height)?;
+        out.write_all(&image.to_rgb8())
+    }
+}
+
+pub struct PngWriter;
+
+impl ImageWriter for PngWriter {
+    fn write_to(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, image.width as u32, image.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&image.to_rgb8()).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+// Portable float map, keeps the linear radiance without any display transform
+pub struct PfmWriter;
+
+impl ImageWriter for PfmWriter {
+    fn write_to(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        // Negative scale marks the data as little endian
+        write!(out, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+
+        // PFM scanlines are stored bottom to top
+        for row in image.pixels.chunks(image.width.max(1)).rev() {
+            for color in row {
+                for c in [color.x, color.y, color.z] {
+                    out.write_all(&(c as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+// OpenEXR with linear R, G, B plus any extra channels of the image
+pub struct ExrWriter {
+    pub precision: ExrPrecision,
+}
+
+impl ExrWriter {
+    fn samples(&self, values: impl Iterator<Item = f32>) -> FlatSamples {
+        match self.precision {
+            ExrPrecision::Half => FlatSamples::F16(values.map(f16::from_f32).collect()),
+            ExrPrecision::Float => FlatSamples::F32(values.collect()),
+        }
+    }
+}
+
+impl Default for ExrWriter {
+    fn default() -> Self {
+        Self { precision: ExrPrecision::Half }
+    }
+}
+
+impl ImageWriter for ExrWriter {
+    fn write_to(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        let pixels = image.pixels();
+
+        let mut channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = SmallVec::new();
+        channels.push(AnyChannel::new("R", self.samples(pixels.iter().map(|c| c.x as f32))));
+        channels.push(AnyChannel::new("G", self.samples(pixels.iter().map(|c| c.y as f32))));
+        channels.push(AnyChannel::new("B", self.samples(pixels.iter().map(|c| c.z as f32))));
+
+        for channel in &image.channels {
+            channels.push(AnyChannel::new(channel.name.as_str(), self.samples(channel.values.iter().copied())));
+        }
+
+        let layer = Layer::new(
+            (image.width, image.height),
+            LayerAttributes::default(),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(channels),
+        );
+
+        // The encoder needs to seek back to fill in the offset table, so assemble the file in memory
+        let mut buffer = Cursor::new(Vec::new());
+        exr::prelude::Image::from_layer(layer).write().to_buffered(&mut buffer).map_err(io::Error::other)?;
+
+        out.write_all(buffer.get_ref())
+    }
+}
+
+// Radiance RGBE (.hdr), stored as flat uncompressed scanlines
+pub struct HdrWriter;
+
+impl ImageWriter for HdrWriter {
+    fn write_to(&self, image: &Image, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height, image.width)?;
+
+        let bytes: Vec<u8> = image.pixels.iter().flat_map(to_rgbe).collect();
+        out.write_all(&bytes)
+    }
+}
+
+// Shared exponent encoding of a linear color, negative components are clamped to zero
+fn to_rgbe(color: &Color) -> [u8; 4] {
+    let r = color.x.max(0.0);
+    let g = color.y.max(0.0);
+    let b = color.z.max(0.0);
+    let v = r.max(g).max(b);
+
+    if v < 1e-32 || !v.is_finite() {
+        return [0, 0, 0, 0];
+    }
+
+    // Find exponent so that v = m * 2^exponent with m in [0.5, 1)
+    let mut exponent = v.log2().floor() as i32 + 1;
+    if v / 2f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let scale = 256.0 / 2f64.powi(exponent);
+
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+// Picks a writer based on the extension of path
+pub fn writer_for_path(path: &Path) -> Option<Box<dyn ImageWriter>> {
+    writer_for_format(&path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+// Picks a writer by format name; names match file extensions, plus exr-float for full precision EXR
+pub fn writer_for_format(format: &str) -> Option<Box<dyn ImageWriter>> {
+    match format {
+        "ppm" => Some(Box::new(PpmWriter)),
+        "png" => Some(Box::new(PngWriter)),
+        "pfm" => Some(Box::new(PfmWriter)),
+        "exr" => Some(Box::new(ExrWriter::default())),
+        "exr-float" => Some(Box::new(ExrWriter { precision: ExrPrecision::Float })),
+        "hdr" => Some(Box::new(HdrWriter)),
+        _ => None,
+    }
+}
+
+// Loads a linear image, picking the decoder from the extension of path
+pub fn load(path: &Path) -> io::Result<Image> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+
+    match extension.as_str() {
+        "hdr" => read_hdr(&mut BufReader::new(File::open(path)?)),
+        "pfm" => read_pfm(&mut BufReader::new(File::open(path)?)),
+        "exr" => read_exr(path),
+        "png" => read_png(path),
+        _ => Err(invalid_data(format!("unsupported image format `{extension}`"))),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_line(input: &mut dyn BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(invalid_data("unexpected end of header".to_string()));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+pub fn read_hdr(input: &mut dyn BufRead) -> io::Result<Image> {
+    if !read_line(input)?.starts_with("#?") {
+        return Err(invalid_data("missing Radiance signature".to_string()));
+    }
+
+    // Header variables end with an empty line
+    loop {
+        let line = read_line(input)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(format!("unsupported Radiance pixel format `{format}`")));
+            }
+        }
+    }
+
+    let resolution = read_line(input)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => (h.parse::<usize>(), w.parse::<usize>()),
+        _ => return Err(invalid_data(format!("unsupported Radiance orientation `{resolution}`"))),
+    };
+    let (height, width) = match (height, width) {
+        (Ok(height), Ok(width)) => (height, width),
+        _ => return Err(invalid_data(format!("invalid Radiance resolution `{resolution}`"))),
+    };
+    if width == 0 || height == 0 || width.checked_mul(height).is_none() {
+        return Err(invalid_data(format!("invalid Radiance resolution `{resolution}`")));
+    }
+
+    // Pixels are collected as they are read, so a corrupt header can't ask for huge amounts of memory
+    let mut pixels = vec![];
+    let mut scanline = vec![];
+
+    for _ in 0..height {
+        read_hdr_scanline(input, width, &mut scanline)?;
+        pixels.extend(scanline.iter().map(from_rgbe));
+    }
+
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+fn read_hdr_scanline(input: &mut dyn BufRead, width: usize, scanline: &mut Vec<[u8; 4]>) -> io::Result<()> {
+    let mut start = [0u8; 4];
+    input.read_exact(&mut start).map_err(truncated)?;
+
+    // Run length encoded scanlines start with 2, 2 and the width, anything else is a flat scanline
+    let is_rle = (8..0x8000).contains(&width) && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0;
+    if !is_rle {
+        let mut bytes = start.to_vec();
+        input.take(((width - 1) as u64).saturating_mul(4)).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != width as u64 * 4 {
+            return Err(invalid_data("unexpected end of Radiance file".to_string()));
+        }
+
+        scanline.clear();
+        scanline.extend(bytes.chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]));
+        return Ok(());
+    }
+
+    if ((start[2] as usize) << 8 | start[3] as usize) != width {
+        return Err(invalid_data("Radiance scanline width mismatch".to_string()));
+    }
+    scanline.resize(width, [0; 4]);
+
+    // Each of the four components is stored separately as runs and literal spans
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            input.read_exact(&mut count).map_err(truncated)?;
+
+            if count[0] > 128 {
+                let run = (count[0] - 128) as usize;
+                let mut value = [0u8; 1];
+                input.read_exact(&mut value).map_err(truncated)?;
+                if x + run > width {
+                    return Err(invalid_data("Radiance run overflows scanline".to_string()));
+                }
+                for pixel in &mut scanline[x..x + run] {
+                    pixel[component] = value[0];
+                }
+                x += run;
+            } else {
+                let span = count[0] as usize;
+                if span == 0 || x + span > width {
+                    return Err(invalid_data("invalid Radiance literal span".to_string()));
+                }
+                let mut values = [0u8; 128];
+                input.read_exact(&mut values[..span]).map_err(truncated)?;
+                for (pixel, value) in scanline[x..x + span].iter_mut().zip(&values[..span]) {
+                    pixel[component] = *value;
+                }
+                x += span;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn truncated(err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => invalid_data("unexpected end of Radiance file".to_string()),
+        _ => err,
+    }
+}
+
+fn from_rgbe(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::ZERO;
+    }
+
+    let scale = 2f64.powi(rgbe[3] as i32 - 128 - 8);
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+pub fn read_pfm(input: &mut dyn BufRead) -> io::Result<Image> {
+    let channels = match read_line(input)?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        other => return Err(invalid_data(format!("invalid PFM signature `{other}`"))),
+    };
+
+    let dimensions = read_line(input)?;
+    let (width, height) = match dimensions.split_whitespace().map(str::parse::<usize>).collect::<Vec<_>>()[..] {
+        [Ok(width), Ok(height)] => (width, height),
+        _ => return Err(invalid_data(format!("invalid PFM dimensions `{dimensions}`"))),
+    };
+    let size = width.checked_mul(height).and_then(|count| count.checked_mul(channels * 4))
+        .filter(|&size| size > 0)
+        .ok_or_else(|| invalid_data(format!("invalid PFM dimensions `{dimensions}`")))?;
+
+    let scale = read_line(input)?;
+    let little_endian = match scale.parse::<f64>() {
+        Ok(scale) => scale < 0.0,
+        Err(_) => return Err(invalid_data(format!("invalid PFM scale `{scale}`"))),
+    };
+
+    // Read rather than allocated up front, so a corrupt header can't ask for huge amounts of memory
+    let mut data = vec![];
+    input.take(size as u64).read_to_end(&mut data)?;
+    if data.len() != size {
+        return Err(invalid_data("unexpected end of PFM file".to_string()));
+    }
+
+    let values: Vec<f64> = data.chunks_exact(4).map(|bytes| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        (if little_endian { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) }) as f64
+    }).collect();
+
+    // Rows are stored bottom to top
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks(width * channels).rev() {
+        pixels.extend(row.chunks(channels).map(|c| if channels == 3 { Color::new(c[0], c[1], c[2]) } else { Color::splat(c[0]) }));
+    }
+
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+// Largest EXR read, enough for a 16k by 8k environment map. The whole image is allocated before
+// any pixel is read, so a corrupt header could otherwise ask for huge amounts of memory.
+const MAX_EXR_PIXELS: usize = 1 << 27;
+
+pub fn read_exr(path: &Path) -> io::Result<Image> {
+    let meta = exr::meta::MetaData::read_from_file(path, false).map_err(|err| invalid_data(err.to_string()))?;
+    for header in meta.headers.iter() {
+        let size = header.layer_size;
+        if size.width().checked_mul(size.height()).is_none_or(|pixels| pixels > MAX_EXR_PIXELS) {
+            return Err(invalid_data(format!("EXR image of {}x{} pixels is too large", size.width(), size.height())));
+        }
+    }
+
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| Image::new(resolution.width(), resolution.height()),
+        |image: &mut Image, position, (r, g, b, _a): (f32, f32, f32, f32)| {
+            image.set(position.x(), position.y(), Color::new(r as f64, g as f64, b as f64));
+        },
+    ).map_err(|err| invalid_data(err.to_string()))?;
+
+    Ok(image.layer_data.channel_data.pixels)
+}
+
+// 8 bit images are assumed to be gamma encoded and are converted back to linear values
+pub fn read_png(path: &Path) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info().map_err(|err| invalid_data(err.to_string()))?;
+    let buffer_size = reader.output_buffer_size().ok_or_else(|| invalid_data("PNG image too large".to_string()))?;
+    let mut data = vec![0; buffer_size];
+    let info = reader.next_frame(&mut data).map_err(|err| invalid_data(err.to_string()))?;
+
+    let channels = info.color_type.samples();
+    let (width, height) = (info.width as usize, info.height as usize);
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in data.chunks(info.line_size).take(height) {
+        for pixel in row.chunks(channels).take(width) {
+            let color = match pixel {
+                [gray] | [gray, _] => Color::splat(gamma_to_linear(*gray)),
+                [r, g, b] | [r, g, b, _] => Color::new(gamma_to_linear(*r), gamma_to_linear(*g), gamma_to_linear(*b)),
+                _ => return Err(invalid_data("unexpected PNG pixel layout".to_string())),
+            };
+            pixels.push(color);
+        }
+    }
+
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+// Inverse of the gamma 2 display transform
+#[inline]
+fn gamma_to_linear(component: u8) -> f64 {
+    let c = component as f64 / 255.0;
+    c * c
+}
+
+#[inline]
+pub fn linear_to_gamma(linear_component: f64) -> f64 {
+    if linear_component > 0.0 {
+        linear_component.sqrt()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Image {
+        let pixels = (0..width * height).map(|i| Color::new(i as f64, 0.5, 0.25 * i as f64)).collect();
+        Image::from_pixels(width, height, pixels)
+    }
+
+    fn encode(writer: &dyn ImageWriter, image: &Image) -> Vec<u8> {
+        let mut bytes = vec![];
+        writer.write_to(image, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn assert_close(a: &Image, b: &Image, tolerance: f64) {
+        assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+        for (p, q) in a.pixels().iter().zip(b.pixels()) {
+            assert!((*p - *q).length() <= tolerance * p.length().max(1.0), "{p:?} != {q:?}");
+        }
+    }
+
+    fn assert_invalid(result: io::Result<Image>, message: &str) {
+        let err = result.err().expect("expected an error");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(message), "`{err}` doesn't mention `{message}`");
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let image = gradient(3, 2);
+        let read = read_pfm(&mut encode(&PfmWriter, &image).as_slice()).unwrap();
+        assert_close(&image, &read, 0.0);
+    }
+
+    #[test]
+    fn pfm_grayscale_big_endian() {
+        let mut bytes = b"Pf\n2 1\n1.0\n".to_vec();
+        bytes.extend(0.5f32.to_be_bytes());
+        bytes.extend(2.0f32.to_be_bytes());
+
+        let read = read_pfm(&mut bytes.as_slice()).unwrap();
+        assert_close(&read, &Image::from_pixels(2, 1, vec![Color::splat(0.5), Color::splat(2.0)]), 0.0);
+    }
+
+    #[test]
+    fn pfm_rejects_malformed_headers() {
+        assert_invalid(read_pfm(&mut &b"P6\n1 1\n255\n"[..]), "signature");
+        assert_invalid(read_pfm(&mut &b"PF\n0 0\n-1.0\n"[..]), "invalid PFM dimensions");
+        assert_invalid(read_pfm(&mut &b"PF\n4 0\n-1.0\n"[..]), "invalid PFM dimensions");
+        assert_invalid(read_pfm(&mut &b"PF\n18446744073709551615 2\n-1.0\n"[..]), "invalid PFM dimensions");
+        assert_invalid(read_pfm(&mut &b"PF\n1 1\nscale\n"[..]), "invalid PFM scale");
+        assert_invalid(read_pfm(&mut &b"PF\n1 1\n"[..]), "unexpected end of header");
+    }
+
+    #[test]
+    fn pfm_rejects_truncated_data() {
+        let bytes = encode(&PfmWriter, &gradient(4, 4));
+        assert_invalid(read_pfm(&mut &bytes[..bytes.len() - 1]), "unexpected end of PFM file");
+
+        // A huge header on a short file fails without trying to allocate the whole image
+        assert_invalid(read_pfm(&mut &b"PF\n100000 100000\n-1.0\n\0\0\0\0"[..]), "unexpected end of PFM file");
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        let image = gradient(5, 3);
+        let read = read_hdr(&mut encode(&HdrWriter, &image).as_slice()).unwrap();
+        assert_close(&image, &read, 1.0 / 64.0);
+    }
+
+    #[test]
+    fn hdr_run_length_encoded() {
+        // One scanline of 8 pixels, each component a single run
+        let mut bytes = b"#?RADIANCE\n\n-Y 1 +X 8\n".to_vec();
+        bytes.extend([2, 2, 0, 8]);
+        for value in [128, 64, 0, 129] {
+            bytes.extend([128 + 8, value]);
+        }
+
+        let read = read_hdr(&mut bytes.as_slice()).unwrap();
+        assert_close(&read, &Image::from_pixels(8, 1, vec![from_rgbe(&[128, 64, 0, 129]); 8]), 0.0);
+    }
+
+    #[test]
+    fn hdr_rejects_malformed_headers() {
+        assert_invalid(read_hdr(&mut &b"P6\n"[..]), "signature");
+        assert_invalid(read_hdr(&mut &b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n"[..]), "pixel format");
+        assert_invalid(read_hdr(&mut &b"#?RADIANCE\n\n+Y 1 +X 1\n"[..]), "orientation");
+        assert_invalid(read_hdr(&mut &b"#?RADIANCE\n\n-Y 0 +X 0\n"[..]), "invalid Radiance resolution");
+        assert_invalid(read_hdr(&mut &b"#?RADIANCE\n\n-Y 1 +X 0\n"[..]), "invalid Radiance resolution");
+        assert_invalid(read_hdr(&mut &b"#?RADIANCE\n\n-Y 18446744073709551615 +X 2\n"[..]), "invalid Radiance resolution");
+    }
+
+    #[test]
+    fn hdr_rejects_truncated_data() {
+        let bytes = encode(&HdrWriter, &gradient(4, 4));
+        assert_invalid(read_hdr(&mut &bytes[..bytes.len() - 2]), "unexpected end of Radiance file");
+        assert_invalid(read_hdr(&mut &b"#?RADIANCE\n\n-Y 100000 +X 100000\n\0\0\0\0"[..]), "unexpected end of Radiance file");
+
+        let mut bytes = b"#?RADIANCE\n\n-Y 1 +X 8\n".to_vec();
+        bytes.extend([2, 2, 0, 8, 128 + 9, 0]);
+        assert_invalid(read_hdr(&mut bytes.as_slice()), "Radiance run overflows scanline");
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let image = gradient(4, 3);
+        let path = std::env::temp_dir().join(format!("raytracer-test-{}.exr", std::process::id()));
+
+        ExrWriter { precision: ExrPrecision::Float }.save(&image, &path).unwrap();
+        let read = read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_close(&image, &read.unwrap(), 0.0);
+    }
+
+    #[test]
+    fn exr_rejects_garbage() {
+        let path = std::env::temp_dir().join(format!("raytracer-test-garbage-{}.exr", std::process::id()));
+
+        std::fs::write(&path, b"not an OpenEXR file").unwrap();
+        let read = read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.err().expect("expected an error").kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn exr_rejects_oversized_header() {
+        let path = std::env::temp_dir().join(format!("raytracer-test-oversized-{}.exr", std::process::id()));
+        ExrWriter::default().save(&gradient(4, 3), &path).unwrap();
+
+        // Stretch the data window to 20000 by 20000 pixels, without the data to go with it
+        let mut bytes = std::fs::read(&path).unwrap();
+        let attribute = b"dataWindow\0box2i\0";
+        let start = bytes.windows(attribute.len()).position(|window| window == attribute).unwrap() + attribute.len() + 4;
+        for (i, value) in [0i32, 0, 19999, 19999].into_iter().enumerate() {
+            bytes[start + 4 * i..start + 4 * i + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        std::fs::write(&path, bytes).unwrap();
+
+        let read = read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_invalid(read, "EXR image of 20000x20000 pixels is too large");
+    }
+}
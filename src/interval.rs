@@ -13,6 +13,14 @@ impl Interval {
         }
     }
 
+    // Creates the tightest interval enclosing both input intervals
+    pub fn from_intervals(a: &Interval, b: &Interval) -> Self {
+        Self {
+            min: f64::min(a.min, b.min),
+            max: f64::max(a.max, b.max),
+        }
+    }
+
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
@@ -5,4 +5,28 @@ pub mod sphere;
 pub mod interval;
 pub mod camera;
 pub mod material;
-pub mod aabb;
\ No newline at end of file
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod image;
+pub mod scene;
+pub mod rng;
+pub mod texture;
+pub mod perlin;
+pub mod quad;
+pub mod mesh;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+pub mod matrix;
+pub mod gltf;
+pub mod transform;
+pub mod quaternion;
+pub mod volume;
+pub mod voxel;
+pub mod onb;
+pub mod microfacet;
+pub mod pdf;
+pub mod light;
+pub mod sky;
+pub mod color;
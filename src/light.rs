@@ -0,0 +1,94 @@
+use crate::vec3::{Color, Vec3};
+
+// Lights without any area. Rays can't hit them, so they only light the scene through shadow rays
+// and are never seen directly or in reflections.
+//
+// Intensities follow glTF's KHR_lights_punctual: point and spot lights give their intensity per
+// steradian, so a surface facing one from distance d receives intensity / d^2, and directional
+// lights give what a surface facing them receives. The emission of diffuse lights is in the same
+// units per unit area, making a small area light of area A about as bright as a point light of
+// intensity emit * A.
+#[derive(Debug, Copy, Clone)]
+pub enum Light {
+    Point { position: Vec3, intensity: Color },
+    // Full intensity within cos_inner of direction, fading out towards cos_outer
+    Spot { position: Vec3, direction: Vec3, intensity: Color, cos_inner: f64, cos_outer: f64 },
+    // Parallel light travelling along direction, like sunlight
+    Directional { direction: Vec3, irradiance: Color },
+}
+
+// Light reaching a point from one of the lights
+pub struct LightSample {
+    // Unit direction from the point towards the light
+    pub direction: Vec3,
+    // Infinite for directional lights
+    pub distance: f64,
+    // Received by a surface facing the light
+    pub irradiance: Color,
+}
+
+impl Light {
+    pub fn point(position: Vec3, intensity: Color) -> Self {
+        Light::Point { position, intensity }
+    }
+
+    // Cone angles are in degrees from the axis, with the falloff between inner and outer
+    pub fn spot(position: Vec3, direction: Vec3, intensity: Color, inner_angle: f64, outer_angle: f64) -> Self {
+        let outer_angle = outer_angle.clamp(0.0, 90.0);
+        let inner_angle = inner_angle.clamp(0.0, outer_angle);
+
+        Light::Spot {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    pub fn directional(direction: Vec3, irradiance: Color) -> Self {
+        Light::Directional { direction: direction.unit_vector(), irradiance }
+    }
+
+    // None when no light from it reaches point
+    pub fn sample(&self, point: Vec3) -> Option<LightSample> {
+        match *self {
+            Light::Point { position, intensity } => Self::towards(point, position, intensity),
+            Light::Spot { position, direction, intensity, cos_inner, cos_outer } => {
+                let sample = Self::towards(point, position, intensity)?;
+
+                // Squared so the edge fades smoothly, as recommended by KHR_lights_punctual
+                let cosine = -sample.direction.dot(&direction);
+                let falloff = if cos_inner > cos_outer {
+                    ((cosine - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0).powi(2)
+                } else if cosine >= cos_outer {
+                    1.0
+                } else {
+                    0.0
+                };
+
+                (falloff > 0.0).then(|| LightSample { irradiance: falloff * sample.irradiance, ..sample })
+            }
+            Light::Directional { direction, irradiance } => Some(LightSample {
+                direction: -direction,
+                distance: f64::INFINITY,
+                irradiance,
+            }),
+        }
+    }
+
+    fn towards(point: Vec3, position: Vec3, intensity: Color) -> Option<LightSample> {
+        let offset = position - point;
+        let distance_squared = offset.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: offset / distance,
+            distance,
+            irradiance: intensity / distance_squared,
+        })
+    }
+}
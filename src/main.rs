@@ -1,71 +1,215 @@
-use rand::{random, thread_rng, Rng};
-use raytracer::camera::Camera;
-use raytracer::hittable::HittableList;
-use raytracer::material::{Dialetric, Lambertian, Material, Metal};
-use raytracer::sphere::Sphere;
-use raytracer::vec3::*;
+use clap::Parser;
+use raytracer::background::{Background, EnvironmentMap};
+use raytracer::bvh::FlatBvh;
+use raytracer::image::{self, writer_for_format, writer_for_path};
+use raytracer::rng;
+use raytracer::scene::{self, Scene, BUILTIN_SCENES};
+use raytracer::vec3::Vec3;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Instant;
 
-fn main() {
-    // World
-
-    let mut world = HittableList { objects: vec![] };
-
-    let material_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-
-    world.add(Sphere::new(Vec3::new(0., -1000., 0.), 1000., material_ground));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat: f64 = random();
-            let center = Vec3::new(a as f64 + 0.9 * random::<f64>(), 0.2, b as f64 + 0.9 * random::<f64>());
-
-            if (center - Vec3::new(4., 0.2, 0.)).length() > 0.9 {
-                let sphere_material: Arc<dyn Material + Send>;
-
-                if choose_mat < 0.8 {
-                    let albedo = Color::random() * Color::random();
-                    sphere_material = Arc::new(Lambertian::new(albedo));
-                    let end_center = center + Vec3::new(0., thread_rng().gen_range(0.0..=0.5), 0.0);
-                    world.add(Sphere::new_moving(center, end_center, 0.2, sphere_material));
-                }
-                else if choose_mat < 0.95 {
-                    let albedo = Color::random_range(0.5, 1.0);
-                    let fuzz = thread_rng().gen_range(0.0..=0.5);
-                    sphere_material = Arc::new(Metal::new(albedo, fuzz));
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                }
-                else {
-                    sphere_material = Arc::new(Dialetric::new(1.5));
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                }
-            }
+#[derive(Parser)]
+#[command(version, about = "Path traces a scene and writes the result to an image file")]
+struct Args {
+    /// Scene file (.toml, .gltf or .glb) or name of a built-in scene
+    #[arg(default_value = "spheres")]
+    scene: String,
+
+    /// Output image path
+    #[arg(short, long, default_value = "image.png")]
+    output: PathBuf,
+
+    /// Output format (ppm, png, pfm, exr, exr-float, hdr), defaults to the output extension
+    #[arg(short, long)]
+    format: Option<String>,
+
+    /// Image width in pixels
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    width: Option<i32>,
+
+    /// Image width divided by height
+    #[arg(long)]
+    aspect_ratio: Option<f64>,
+
+    /// Samples per pixel
+    #[arg(short, long, value_parser = clap::value_parser!(i32).range(1..))]
+    samples: Option<i32>,
+
+    /// Maximum number of ray bounces, as a safety cap on paths Russian roulette hasn't ended
+    #[arg(long, value_parser = clap::value_parser!(i32).range(0..))]
+    max_depth: Option<i32>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    vfov: Option<f64>,
+
+    /// Camera position as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    lookfrom: Option<Vec3>,
+
+    /// Point the camera looks at as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    lookat: Option<Vec3>,
+
+    /// Variation angle of rays through each pixel in degrees, 0 disables depth of field
+    #[arg(long)]
+    defocus_angle: Option<f64>,
+
+    /// Distance from the camera to the plane of perfect focus
+    #[arg(long)]
+    focus_distance: Option<f64>,
+
+    /// Equirectangular HDR image (.hdr, .exr, .pfm) to light the scene with instead of its background
+    #[arg(long)]
+    environment: Option<PathBuf>,
+
+    /// Rotation of the environment map around the up axis in degrees
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    environment_rotation: f64,
+
+    /// Multiplier for the environment map radiance
+    #[arg(long, default_value_t = 1.0)]
+    environment_intensity: f64,
+
+    /// Also write depth and normal channels (EXR only)
+    #[arg(long)]
+    aovs: bool,
+
+    /// Number of render threads, defaults to one per core
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Render on the main thread and report progress
+    #[arg(long)]
+    single_thread: bool,
+
+    /// Seed for scene generation and sampling, makes renders reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// List the built-in scenes and exit
+    #[arg(long)]
+    list_scenes: bool,
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let components: Vec<f64> = s.split(',')
+        .map(|c| c.trim().parse::<f64>().map_err(|err| format!("invalid component `{c}`: {err}")))
+        .collect::<Result<_, _>>()?;
+
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("expected three comma separated components, got {}", components.len())),
+    }
+}
+
+fn load_scene(name: &str) -> Result<Scene, String> {
+    if let Some(scene) = scene::builtin(name) {
+        return Ok(scene);
+    }
+
+    let path = Path::new(name);
+    if !path.exists() {
+        return Err(format!("`{name}` is neither a scene file nor a built-in scene ({})", BUILTIN_SCENES.join(", ")));
+    }
+
+    scene::load(path).map_err(|err| err.to_string())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    if args.list_scenes {
+        for name in BUILTIN_SCENES {
+            println!("{name}");
         }
+        return ExitCode::SUCCESS;
     }
 
-    let glass = Arc::new(Dialetric::new(1.5));
-    let diffuse = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    let metal = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let writer = match &args.format {
+        Some(format) => writer_for_format(format),
+        None => writer_for_path(&args.output),
+    };
+    let Some(writer) = writer else {
+        eprintln!("Cannot determine image format for {}, use --format", args.output.display());
+        return ExitCode::FAILURE;
+    };
 
-    world.add(Sphere::new(Vec3::new(0., 1., 0.), 1.0, glass));
-    world.add(Sphere::new(Vec3::new(-4., 1., 0.), 1.0, diffuse));
-    world.add(Sphere::new(Vec3::new(4., 1., 0.), 1.0, metal));
+    if let Some(threads) = args.threads {
+        if let Err(err) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            eprintln!("Failed to set up render threads: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
 
+    if let Some(seed) = args.seed {
+        rng::seed(seed);
+    }
 
-    let mut camera = Camera::new();
+    let Scene { world, lights, punctual_lights, mut camera } = match load_scene(&args.scene) {
+        Ok(scene) => scene,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Some(width) = args.width { camera.width = width; }
+    if let Some(aspect_ratio) = args.aspect_ratio { camera.aspect_ratio = aspect_ratio; }
+    if let Some(samples) = args.samples { camera.samples_per_pixel = samples; }
+    if let Some(max_depth) = args.max_depth { camera.max_depth = max_depth; }
+    if let Some(vfov) = args.vfov { camera.vfov = vfov; }
+    if let Some(lookfrom) = args.lookfrom { camera.lookfrom = lookfrom; }
+    if let Some(lookat) = args.lookat { camera.lookat = lookat; }
+    if let Some(defocus_angle) = args.defocus_angle { camera.defocus_angle = defocus_angle; }
+    if let Some(focus_distance) = args.focus_distance { camera.focus_distance = focus_distance; }
+    if let Some(path) = &args.environment {
+        let image = match image::load(path) {
+            Ok(image) => image,
+            Err(err) => {
+                eprintln!("Failed to load environment map {}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let mut map = EnvironmentMap::new(Arc::new(image));
+        map.rotation = args.environment_rotation;
+        map.intensity = args.environment_intensity;
+        camera.background = Background::Environment(map);
+    }
+    camera.aov_channels = args.aovs;
+    camera.single_thread = args.single_thread;
+    camera.seed = args.seed;
 
-    camera.aspect_ratio = 16.0 / 9.0;
-    camera.width = 400;
-    camera.samples_per_pixel = 100;
-    camera.max_depth = 50;
+    let world = FlatBvh::new(world);
+    let bvh_stats = world.stats();
 
-    camera.vfov = 20.0;
-    camera.lookfrom = Vec3::new(13., 2., 3.);
-    camera.lookat = Vec3::new(0., 0., 0.);
-    camera.vup = Vec3::new(0., 1., 0.);
+    let start = Instant::now();
+    let image = match camera.render(&world, &lights, &punctual_lights) {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("Cannot render {}: {err}", args.scene);
+            return ExitCode::FAILURE;
+        }
+    };
+    let render_time = start.elapsed();
+
+    let samples = (image.width() * image.height()) as f64 * camera.samples_per_pixel as f64;
+
+    eprintln!("Primitives:  {}", bvh_stats.primitive_count);
+    eprintln!("BVH:         {} nodes, {} leaves, depth {}, SAH cost {:.2}, built in {:.2?}",
+              bvh_stats.node_count, bvh_stats.leaf_count, bvh_stats.max_depth, bvh_stats.sah_cost, bvh_stats.build_time);
+    eprintln!("Resolution:  {}x{}, {} samples per pixel", image.width(), image.height(), camera.samples_per_pixel);
+    eprintln!("Render time: {:.2?} ({:.2} M samples/s)", render_time, samples / render_time.as_secs_f64() / 1e6);
+
+    if let Err(err) = writer.save(&image, &args.output) {
+        eprintln!("Failed to write {}: {err}", args.output.display());
+        return ExitCode::FAILURE;
+    }
 
-    camera.defocus_angle = 0.6;
-    camera.focus_distance = 10.0;
+    eprintln!("Wrote {}", args.output.display());
 
-    camera.render(&world);
-}
\ No newline at end of file
+    ExitCode::SUCCESS
+}
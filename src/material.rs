@@ -1,68 +1,146 @@
-use rand::random;
 use crate::hittable::HitRecord;
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, Ggx};
+use crate::onb::Onb;
+use crate::pdf::{random_cosine_direction, CosinePdf, Pdf, SpherePdf};
 use crate::ray::Ray;
+use crate::rng::random;
+use crate::texture::{SolidColor, Texture};
+use std::f64::consts::PI;
+use std::sync::Arc;
 use crate::vec3::{Color, Vec3};
 
+// How a material scatters a ray that hit it
+pub enum ScatterRecord {
+    // The material picked the scattered ray itself, as mirrors and glass do, with the attenuation
+    // already accounting for that choice. There's no density to weigh other samples against.
+    Specular { ray: Ray, attenuation: Color },
+    // The direction is drawn from pdf and weighted by the material's eval over the density
+    Pdf(Box<dyn Pdf>),
+}
+
 pub trait Material: Sync {
-    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
         None
     }
+
+    // Fraction of light arriving from direction that is scattered back along ray: the BSDF times
+    // the cosine between direction and the normal. Only covers what scatter samples with a pdf.
+    fn eval(&self, _ray: &Ray, _hit: &HitRecord, _direction: Vec3) -> Color {
+        Color::ZERO
+    }
+
+    fn emitted(&self, _hit: &HitRecord) -> Color {
+        Color::ZERO
+    }
+}
+
+// Texture value at the hit, tinted by the vertex color of meshes that carry one
+fn albedo(texture: &dyn Texture, hit: &HitRecord) -> Color {
+    let albedo = texture.value(hit.u, hit.v, &hit.point);
+    hit.color.map_or(albedo, |color| albedo * color)
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    texture: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(texture: Arc<dyn Texture>) -> Self {
         Lambertian {
-            albedo,
+            texture,
         }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Color)> {
-        let mut direction = hit.normal + random_unit_vector();
-
-        // catch degenerate scatter direction
-        if direction.near_zero() {
-            direction = hit.normal;
-        }
-
-        let scattered = Ray::new(hit.point, direction, ray.time);
-        let attenuation = self.albedo;
+    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(CosinePdf::new(hit.normal))))
+    }
 
-        Some((scattered, attenuation))
+    fn eval(&self, _ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        let cos_theta = hit.normal.dot(&direction.unit_vector());
+        albedo(self.texture.as_ref(), hit) * f64::max(0.0, cos_theta / PI)
     }
 }
 
 pub struct Metal {
-    albedo: Color,
+    texture: Arc<dyn Texture>,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn from_texture(texture: Arc<dyn Texture>, fuzz: f64) -> Self {
         Metal {
-            albedo,
+            texture,
             fuzz: if fuzz < 1. { fuzz } else { 1. },
         }
     }
 }
 
+impl Metal {
+    fn fuzz_pdf(&self, ray: &Ray, hit: &HitRecord) -> FuzzPdf {
+        FuzzPdf {
+            reflected: reflect(&ray.direction, &hit.normal).unit_vector(),
+            fuzz: self.fuzz,
+        }
+    }
+}
+
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Color)> {
-        let reflected = reflect(&ray.direction, &hit.normal);
-        let reflected = reflected.unit_vector() + (self.fuzz * random_unit_vector());
-        let scattered = Ray::new(hit.point, reflected, ray.time);
-        let attenuation = self.albedo;
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        // Without fuzz the reflection is a perfect mirror
+        if self.fuzz <= 0.0 {
+            let scattered = Ray::new(hit.point, reflect(&ray.direction, &hit.normal), ray.time);
+            return Some(ScatterRecord::Specular { ray: scattered, attenuation: albedo(self.texture.as_ref(), hit) });
+        }
 
-        if scattered.direction.dot(&hit.normal) < 0. {
-            return None;
+        Some(ScatterRecord::Pdf(Box::new(self.fuzz_pdf(ray, hit))))
+    }
+
+    // Reflects the albedo in proportion to the fuzz distribution, with whatever it sends below the
+    // surface absorbed
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        if direction.dot(&hit.normal) <= 0.0 {
+            return Color::ZERO;
+        }
+
+        albedo(self.texture.as_ref(), hit) * self.fuzz_pdf(ray, hit).value(direction)
+    }
+}
+
+// Directions of the mirror reflection pushed by a random offset of length fuzz, which points them
+// anywhere inside a cone around it
+struct FuzzPdf {
+    reflected: Vec3,
+    fuzz: f64,
+}
+
+impl Pdf for FuzzPdf {
+    // The offset tips of a direction lie where its line crosses the sphere of radius fuzz around
+    // the reflection, at distances t from the origin. Each crossing adds the chance of hitting it,
+    // t^2 / (4 pi fuzz^2), over the cosine between the line and the sphere there.
+    fn value(&self, direction: Vec3) -> f64 {
+        let c = direction.unit_vector().dot(&self.reflected);
+        let discriminant = c * c - 1.0 + self.fuzz * self.fuzz;
+        if c <= 0.0 || discriminant <= 0.0 {
+            return 0.0;
         }
 
-        Some((scattered, attenuation))
+        let root = discriminant.sqrt();
+        let (t1, t2) = (c - root, c + root);
+        (t1 * t1 + t2 * t2) / (4.0 * PI * self.fuzz * root)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.reflected + self.fuzz * random_unit_vector()
     }
 }
 
@@ -85,7 +163,7 @@ impl Dialetric {
 }
 
 impl Material for Dialetric {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
         let attenuation = Color::ONE;
         let ri = if hit.front_face {
             1.0 / self.refraction_index
@@ -98,24 +176,482 @@ impl Material for Dialetric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_reflect = ri * sin_theta > 1.0;
-        let direction: Vec3;
 
-        if cannot_reflect || Self::reflectance(cos_theta, ri) > random::<f64>() {
-            direction = reflect(&unit_direction, &hit.normal);
+        let direction = if cannot_reflect || Self::reflectance(cos_theta, ri) > random::<f64>() {
+            reflect(&unit_direction, &hit.normal)
         }
         else {
-            direction = refract(&unit_direction, &hit.normal, ri);
-        }
+            refract(&unit_direction, &hit.normal, ri)
+        };
 
 
         let scattered = Ray::new(hit.point, direction, ray.time);
 
-        Some((scattered, attenuation))
+        Some(ScatterRecord::Specular { ray: scattered, attenuation })
+    }
+}
+
+// Metals with measured complex refractive indices at red, green and blue wavelengths
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MetalKind {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl MetalKind {
+    // Real part eta and imaginary part k of the refractive index
+    pub fn refractive_index(self) -> (Color, Color) {
+        match self {
+            MetalKind::Gold => (Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603)),
+            MetalKind::Copper => (Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142)),
+            MetalKind::Aluminium => (Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837)),
+            MetalKind::Silver => (Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147)),
+        }
+    }
+}
+
+// Rough metal made of GGX microfacets, each a perfect mirror reflecting by the exact Fresnel
+// equations for a conductor. Light bouncing between microfacets more than once is lost, so very
+// rough surfaces come out slightly darker than they should.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: Ggx::new(roughness),
+        }
+    }
+
+    pub fn from_metal(metal: MetalKind, roughness: f64) -> Self {
+        let (eta, k) = metal.refractive_index();
+        Self::new(eta, k, roughness)
+    }
+}
+
+impl Conductor {
+    fn layers(&self, ray: &Ray, hit: &HitRecord) -> Layers {
+        let glossy = Lobe::Glossy { distribution: self.distribution, fresnel: Fresnel::Conductor { eta: self.eta, k: self.k } };
+        Layers::new(ray, hit, vec![(glossy, Color::ONE, 1.0)])
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        let layers = self.layers(ray, hit);
+        if layers.wo.z <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::Pdf(Box::new(layers)))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.layers(ray, hit).eval(direction)
+    }
+}
+
+// Glass with a rough surface made of GGX microfacets, like frosted or etched glass. Each microfacet
+// reflects or refracts by the exact Fresnel equations.
+pub struct RoughDielectric {
+    refraction_index: f64,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: f64) -> Self {
+        Self {
+            refraction_index,
+            distribution: Ggx::new(roughness),
+        }
+    }
+}
+
+impl RoughDielectric {
+    fn layers(&self, ray: &Ray, hit: &HitRecord) -> Layers {
+        // Index on the far side over the index on the side the ray comes from
+        let eta = if hit.front_face { self.refraction_index } else { 1.0 / self.refraction_index };
+
+        let glass = Lobe::Dielectric { distribution: self.distribution, eta, tint: Color::ONE };
+        Layers::new(ray, hit, vec![(glass, Color::ONE, 1.0)])
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        let layers = self.layers(ray, hit);
+        if layers.wo.z <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::Pdf(Box::new(layers)))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.layers(ray, hit).eval(direction)
+    }
+}
+
+// One material for most real surfaces, in the style of the Disney principled BRDF and glTF's
+// metallic-roughness model. Every parameter is a texture, with scalar parameters read from the red
+// channel. Scattering picks one layer at random: the clearcoat, then a metal, glass or plastic-like
+// base by metallic and transmission, with the chances matching the weight of each layer so no
+// extra weighting is needed.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    // 0 for dielectrics, 1 for metals reflecting in the base color
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // Reflectance of dielectrics facing the viewer, where 0.5 gives the 4% of most materials
+    pub specular: Arc<dyn Texture>,
+    // Extra white reflection of the diffuse layer towards grazing angles, as on cloth
+    pub sheen: Arc<dyn Texture>,
+    // Weight and roughness of a clear varnish layer on top of everything else
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    // 0 for opaque surfaces, 1 for glass tinted by the base color
+    pub transmission: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+    // Refractive index for transmission
+    pub refraction_index: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(base_color)))
+    }
+
+    pub fn from_texture(base_color: Arc<dyn Texture>) -> Self {
+        let constant = |value: f64| -> Arc<dyn Texture> { Arc::new(SolidColor::new(Color::splat(value))) };
+
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            clearcoat_roughness: constant(0.03),
+            transmission: constant(0.0),
+            emission: constant(0.0),
+            refraction_index: 1.5,
+        }
+    }
+}
+
+impl Principled {
+    fn scalar(&self, texture: &Arc<dyn Texture>, hit: &HitRecord) -> f64 {
+        texture.value(hit.u, hit.v, &hit.point).x.clamp(0.0, 1.0)
+    }
+
+    fn layers(&self, ray: &Ray, hit: &HitRecord) -> Layers {
+        let base_color = albedo(self.base_color.as_ref(), hit);
+        let metallic = self.scalar(&self.metallic, hit);
+        let distribution = Ggx::new(self.scalar(&self.roughness, hit));
+
+        // Rough glass, tinted by the base color when refracting
+        let transmission = self.scalar(&self.transmission, hit);
+        let eta = if hit.front_face { self.refraction_index } else { 1.0 / self.refraction_index };
+        let glass = Lobe::Dielectric { distribution, eta, tint: base_color };
+
+        // Leaving a transparent object, only the glass layer makes sense
+        if !hit.front_face && transmission > 0.0 {
+            return Layers::new(ray, hit, vec![(glass, Color::ONE, 1.0)]);
+        }
+
+        let cos_o = -ray.direction.unit_vector().dot(&hit.normal);
+        let clearcoat = self.scalar(&self.clearcoat, hit) * fresnel_dielectric(cos_o, 1.5);
+
+        // Index of refraction with the reflectance asked for by specular
+        let f0 = (0.08 * self.scalar(&self.specular, hit)).min(0.99).sqrt();
+        let eta = (1.0 + f0) / (1.0 - f0);
+        let specular = fresnel_dielectric(cos_o, eta);
+
+        let base = 1.0 - clearcoat;
+        let dielectric = base * (1.0 - metallic);
+
+        let lobes = vec![
+            (
+                Lobe::Glossy { distribution: Ggx::new(self.scalar(&self.clearcoat_roughness, hit)), fresnel: Fresnel::One },
+                Color::splat(clearcoat),
+                clearcoat,
+            ),
+            // Schlick's approximation, since metals here are described by their color rather than
+            // a complex refractive index
+            (Lobe::Glossy { distribution, fresnel: Fresnel::Schlick(base_color) }, Color::splat(base * metallic), base * metallic),
+            (Lobe::Glossy { distribution, fresnel: Fresnel::Dielectric(eta) }, Color::splat(dielectric), dielectric * specular),
+            // Diffuse light from below the specular reflection, with white sheen on top that the
+            // diffuse lobe's samples cover as well
+            (Lobe::Diffuse, dielectric * (1.0 - specular) * base_color, dielectric * (1.0 - specular)),
+            (Lobe::Sheen(self.scalar(&self.sheen, hit)), Color::splat(dielectric * (1.0 - specular)), 0.0),
+        ];
+
+        // The opaque layers share what transmission leaves of the surface
+        let opaque = 1.0 - transmission;
+        let mut lobes: Vec<_> = lobes.into_iter().map(|(lobe, weight, p)| (lobe, opaque * weight, opaque * p)).collect();
+        if transmission > 0.0 {
+            lobes.push((glass, Color::splat(transmission), transmission));
+        }
+
+        Layers::new(ray, hit, lobes)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        let layers = self.layers(ray, hit);
+        if layers.wo.z <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord::Pdf(Box::new(layers)))
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.layers(ray, hit).eval(direction)
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> Color {
+        self.emission.value(hit.u, hit.v, &hit.point)
+    }
+}
+
+// Fresnel reflectance of a glossy lobe
+enum Fresnel {
+    One,
+    Conductor { eta: Color, k: Color },
+    // Approximation from the reflectance facing the viewer
+    Schlick(Color),
+    Dielectric(f64),
+}
+
+impl Fresnel {
+    fn value(&self, cos_i: f64) -> Color {
+        match self {
+            Fresnel::One => Color::ONE,
+            Fresnel::Conductor { eta, k } => fresnel_conductor(cos_i, *eta, *k),
+            Fresnel::Schlick(f0) => *f0 + (Color::ONE - *f0) * (1.0 - cos_i).clamp(0.0, 1.0).powi(5),
+            Fresnel::Dielectric(eta) => Color::splat(fresnel_dielectric(cos_i, *eta)),
+        }
+    }
+}
+
+// Scattering lobe, working with directions in the local frame of the surface
+enum Lobe {
+    // GGX microfacets, sampled by their visible normals
+    Glossy { distribution: Ggx, fresnel: Fresnel },
+    // Lambertian, sampled by the cosine. The color is applied by the lobe's weight.
+    Diffuse,
+    // Reflection of the given strength growing towards grazing angles, as on cloth
+    Sheen(f64),
+    // Rough boundary between two dielectrics, reflecting and refracting off GGX microfacets as
+    // their Fresnel term says. Eta is the index on the far side over the one wo is on, and the tint
+    // only applies to refracted light.
+    Dielectric { distribution: Ggx, eta: f64, tint: Color },
+}
+
+impl Lobe {
+    // BSDF times the cosine of wi
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        match self {
+            Lobe::Dielectric { distribution, eta, tint } => match refraction_normal(wo, wi, *eta) {
+                None => {
+                    let h = (wo + wi).unit_vector();
+                    Color::splat(fresnel_dielectric(wo.dot(&h), *eta) * distribution.d(h) * distribution.g2(wo, wi) / (4.0 * wo.z))
+                }
+                Some((h, jacobian)) => {
+                    let transmitted = 1.0 - fresnel_dielectric(wo.dot(&h), *eta);
+                    *tint * (transmitted * distribution.d(h) * distribution.g2(wo, wi) * wo.dot(&h) / wo.z * jacobian)
+                }
+            },
+            // The other lobes only reflect
+            _ if wi.z <= 0.0 => Color::ZERO,
+            Lobe::Glossy { distribution, fresnel } => {
+                let h = (wo + wi).unit_vector();
+                fresnel.value(wo.dot(&h)) * (distribution.d(h) * distribution.g2(wo, wi) / (4.0 * wo.z))
+            }
+            Lobe::Diffuse => Color::splat(wi.z / PI),
+            Lobe::Sheen(sheen) => {
+                let h = (wo + wi).unit_vector();
+                Color::splat(sheen * (1.0 - wi.dot(&h).clamp(0.0, 1.0)).powi(5) * wi.z / PI)
+            }
+        }
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        match self {
+            Lobe::Dielectric { distribution, eta, .. } => match refraction_normal(wo, wi, *eta) {
+                None => {
+                    let h = (wo + wi).unit_vector();
+                    fresnel_dielectric(wo.dot(&h), *eta) * distribution.d(h) * distribution.g1(wo) / (4.0 * wo.z)
+                }
+                Some((h, jacobian)) => {
+                    let transmitted = 1.0 - fresnel_dielectric(wo.dot(&h), *eta);
+                    transmitted * distribution.d(h) * distribution.g1(wo) * wo.dot(&h) / wo.z * jacobian
+                }
+            },
+            _ if wi.z <= 0.0 => 0.0,
+            Lobe::Glossy { distribution, .. } => {
+                let h = (wo + wi).unit_vector();
+                distribution.d(h) * distribution.g1(wo) / (4.0 * wo.z)
+            }
+            Lobe::Diffuse | Lobe::Sheen(_) => wi.z / PI,
+        }
+    }
+
+    // None when the sample ends up on the wrong side of the surface, which absorbs it
+    fn sample(&self, wo: Vec3) -> Option<Vec3> {
+        match self {
+            Lobe::Glossy { distribution, .. } => {
+                Some(reflect(&-wo, &distribution.sample_visible_normal(wo))).filter(|wi| wi.z > 0.0)
+            }
+            Lobe::Diffuse | Lobe::Sheen(_) => Some(random_cosine_direction()),
+            Lobe::Dielectric { distribution, eta, .. } => {
+                let m = distribution.sample_visible_normal(wo);
+                if random::<f64>() < fresnel_dielectric(wo.dot(&m), *eta) {
+                    Some(reflect(&-wo, &m)).filter(|wi| wi.z > 0.0)
+                } else {
+                    Some(refract(&-wo, &m, 1.0 / eta)).filter(|wi| wi.z < 0.0)
+                }
+            }
+        }
     }
 }
 
+// Microfacet normal that refracts wo into wi below the surface, along with the change of density
+// from microfacet normals to refracted directions (Walter et al. 2007). None if wi is above the
+// surface or no microfacet facing wo refracts into it.
+fn refraction_normal(wo: Vec3, wi: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    if wi.z >= 0.0 {
+        return None;
+    }
+
+    let h = (wo + eta * wi).unit_vector();
+    let h = if h.z < 0.0 { -h } else { h };
+
+    let (cos_o, cos_i) = (wo.dot(&h), wi.dot(&h));
+    if cos_o <= 0.0 || cos_i >= 0.0 {
+        return Some((h, 0.0));
+    }
+
+    let denominator = cos_o + eta * cos_i;
+    Some((h, eta * eta * -cos_i / (denominator * denominator)))
+}
+
+// Scattering off a stack of layers at a hit, each lobe weighted in the BSDF and chosen with its own
+// probability when sampling. The probabilities add up to one, and the density covers all lobes so
+// any lobe's sample is weighted correctly against the others.
+struct Layers {
+    frame: Onb,
+    wo: Vec3,
+    lobes: Vec<(Lobe, Color, f64)>,
+}
+
+impl Layers {
+    fn new(ray: &Ray, hit: &HitRecord, lobes: Vec<(Lobe, Color, f64)>) -> Self {
+        let frame = Onb::new(hit.normal);
+
+        Self {
+            wo: frame.to_local(-ray.direction.unit_vector()),
+            frame,
+            lobes,
+        }
+    }
+
+    // Local direction for a world one, None if wo is below the surface or direction lies in it
+    fn local(&self, direction: Vec3) -> Option<Vec3> {
+        let wi = self.frame.to_local(direction.unit_vector());
+        (wi.z.abs() > 0.0 && self.wo.z > 0.0).then_some(wi)
+    }
+
+    fn eval(&self, direction: Vec3) -> Color {
+        let Some(wi) = self.local(direction) else {
+            return Color::ZERO;
+        };
+
+        self.lobes.iter().fold(Color::ZERO, |sum, (lobe, weight, _)| sum + *weight * lobe.eval(self.wo, wi))
+    }
+}
+
+impl Pdf for Layers {
+    fn value(&self, direction: Vec3) -> f64 {
+        let Some(wi) = self.local(direction) else {
+            return 0.0;
+        };
+
+        self.lobes.iter().filter(|(_, _, p)| *p > 0.0).map(|(lobe, _, p)| p * lobe.pdf(self.wo, wi)).sum()
+    }
+
+    fn generate(&self) -> Vec3 {
+        let mut choice = random::<f64>();
+        let lobe = self.lobes.iter()
+            .find(|(_, _, p)| {
+                choice -= p;
+                choice < 0.0
+            })
+            .or(self.lobes.last())
+            .map(|(lobe, _, _)| lobe)
+            .unwrap();
+
+        // A failed sample comes out as the zero vector, which has no density
+        lobe.sample(self.wo).map_or(Vec3::ZERO, |wi| self.frame.to_world(wi))
+    }
+}
+
+// Emits light uniformly from the surface without scattering any incoming light
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self {
+            emit,
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, _hit: &HitRecord) -> Color {
+        self.emit
+    }
+}
+
+// Phase function of participating media, scattering equally in every direction
+pub struct Isotropic {
+    texture: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(texture: Arc<dyn Texture>) -> Self {
+        Isotropic {
+            texture,
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _ray: &Ray, _hit: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf(Box::new(SpherePdf)))
+    }
+
+    fn eval(&self, _ray: &Ray, hit: &HitRecord, _direction: Vec3) -> Color {
+        albedo(self.texture.as_ref(), hit) / (4.0 * PI)
+    }
+}
 
-fn random_unit_vector() -> Vec3 {
+pub fn random_unit_vector() -> Vec3 {
     loop {
         let p = Vec3::random_range(-1., 1.);
         let lensq = p.length_squared();
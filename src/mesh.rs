@@ -0,0 +1,212 @@
+use crate::aabb::AABB;
+use crate::bvh::FlatBvh;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::{obj, ply, stl};
+use crate::ray::Ray;
+use crate::vec3::{Color, Vec3};
+use std::fmt::{self, Display};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Vertex and index buffers shared by every triangle of a mesh
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    // Either empty or one per position
+    pub normals: Vec<Vec3>,
+    // Either empty or one per position
+    pub uvs: Vec<[f64; 2]>,
+    // Either empty or one per position
+    pub colors: Vec<Color>,
+    pub indices: Vec<[u32; 3]>,
+    pub material: Arc<dyn Material + Send>,
+}
+
+// A single face of a mesh, referencing the shared buffers instead of owning its vertices
+pub struct Triangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl Triangle {
+    pub fn new(mesh: Arc<MeshData>, face: usize) -> Self {
+        Self {
+            mesh,
+            face,
+        }
+    }
+
+    fn vertices(&self) -> [usize; 3] {
+        self.mesh.indices[self.face].map(|i| i as usize)
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // Möller–Trumbore intersection
+        let [i0, i1, i2] = self.vertices();
+        let p0 = self.mesh.positions[i0];
+        let edge1 = self.mesh.positions[i1] - p0;
+        let edge2 = self.mesh.positions[i2] - p0;
+
+        let pvec = ray.direction.cross(&edge2);
+        let det = edge1.dot(&pvec);
+
+        // No hit if the ray is parallel to the triangle
+        if det.abs() < 1e-12 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let tvec = ray.origin - p0;
+
+        let b1 = tvec.dot(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(&edge1);
+        let b2 = ray.direction.dot(&qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(&qvec) * inv_det;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+
+        let (u, v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let [uv0, uv1, uv2] = [self.mesh.uvs[i0], self.mesh.uvs[i1], self.mesh.uvs[i2]];
+            (b0 * uv0[0] + b1 * uv1[0] + b2 * uv2[0], b0 * uv0[1] + b1 * uv1[1] + b2 * uv2[1])
+        };
+
+        let colors = &self.mesh.colors;
+        let color = (!colors.is_empty()).then(|| b0 * colors[i0] + b1 * colors[i1] + b2 * colors[i2]);
+
+        let mut hr = HitRecord {
+            point: ray.at(t),
+            normal: Vec3::ZERO,
+            material: Arc::clone(&self.mesh.material),
+            t,
+            u,
+            v,
+            color,
+            front_face: false,
+        };
+
+        // Sidedness comes from the geometric normal, shading uses the interpolated vertex normals
+        let geometric_normal = edge1.cross(&edge2).unit_vector();
+        hr.set_face_normal(ray, geometric_normal);
+
+        if !self.mesh.normals.is_empty() {
+            let normals = &self.mesh.normals;
+            let shading_normal = (b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]).unit_vector();
+            hr.normal = if hr.front_face { shading_normal } else { -shading_normal };
+        }
+
+        Some(hr)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let [i0, i1, i2] = self.vertices();
+        let positions = &self.mesh.positions;
+
+        AABB::from_boxes(
+            &AABB::from_points(positions[i0], positions[i1]),
+            &AABB::from_points(positions[i2], positions[i2]),
+        ).pad_to_minimums(0.0001)
+    }
+}
+
+// All triangles of a mesh behind their own BVH, so the mesh can be added to a scene as one object
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: FlatBvh,
+}
+
+impl TriangleMesh {
+    pub fn new(data: Arc<MeshData>) -> Self {
+        let mut triangles = HittableList { objects: Vec::with_capacity(data.indices.len()) };
+        for face in 0..data.indices.len() {
+            triangles.add(Triangle::new(Arc::clone(&data), face));
+        }
+
+        Self {
+            data,
+            bvh: FlatBvh::new(triangles),
+        }
+    }
+
+    pub fn data(&self) -> &Arc<MeshData> {
+        &self.data
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.data.indices.len()
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        self.bvh.hit(ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bvh.bounding_box()
+    }
+}
+
+// Loads a mesh file, picking the format by extension. Faces without a material of their own use
+// default_material.
+pub fn load(path: &Path, default_material: Arc<dyn Material + Send>) -> Result<HittableList, MeshError> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+
+    let data = match extension.as_str() {
+        "obj" => return obj::load_obj(path, default_material),
+        "ply" => ply::load_ply(path, default_material)?,
+        "stl" => stl::load_stl(path, default_material)?,
+        _ => return Err(MeshError::parse(None, format!("unsupported mesh format `{extension}`"))),
+    };
+
+    let mut list = HittableList { objects: vec![] };
+    list.add(TriangleMesh::new(Arc::new(data)));
+    Ok(list)
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Io(io::Error),
+    // Malformed file contents, with the line number for text formats when known
+    Parse { line: Option<usize>, message: String },
+}
+
+impl MeshError {
+    pub fn parse(line: Option<usize>, message: impl Into<String>) -> Self {
+        MeshError::Parse { line, message: message.into() }
+    }
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::Io(err) => write!(f, "{err}"),
+            MeshError::Parse { line: Some(line), message } => write!(f, "line {line}: {message}"),
+            MeshError::Parse { line: None, message } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+impl From<io::Error> for MeshError {
+    fn from(err: io::Error) -> Self {
+        MeshError::Io(err)
+    }
+}
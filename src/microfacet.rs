@@ -0,0 +1,188 @@
+use crate::rng::random;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Sub};
+
+// Distribution of microfacet normals on a rough surface (GGX, also known as Trowbridge-Reitz).
+// Directions are in the local frame of the surface, with the normal along +z.
+#[derive(Debug, Copy, Clone)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // Roughness is perceptual, from 0 for a mirror to 1 for very rough, and squared into the
+    // distribution's alpha. It never reaches zero, where the distribution becomes a spike.
+    pub fn new(roughness: f64) -> Self {
+        Self {
+            alpha: (roughness * roughness).clamp(1e-4, 1.0),
+        }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    // Density of microfacets with normal m, per unit of projected area
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        let denominator = m.z * m.z * (a2 - 1.0) + 1.0;
+        a2 / (PI * denominator * denominator)
+    }
+
+    // Smith's auxiliary function, how much of the surface microfacets hide when seen from w
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    // Fraction of microfacets visible from w
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Fraction of microfacets visible from both directions, with the correlation between the two
+    // heights taken into account
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Samples a microfacet normal in proportion to how much of it is visible from wo, which must be
+    // above the surface (Heitz 2018). Scattering off it then only needs weighting by g2 / g1(wo).
+    pub fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        // Stretch the view direction so the distribution becomes a hemisphere
+        let view = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).unit_vector();
+
+        let length2 = view.x * view.x + view.y * view.y;
+        let t1 = if length2 > 0.0 { Vec3::new(-view.y, view.x, 0.0) / length2.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = view.cross(&t1);
+
+        // Point on the disk the visible hemisphere projects to, squeezed into its visible part
+        let radius = random::<f64>().sqrt();
+        let phi = 2.0 * PI * random::<f64>();
+        let p1 = radius * phi.cos();
+        let s = 0.5 * (1.0 + view.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * radius * phi.sin();
+
+        let normal = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * view;
+
+        // Unstretch back to the actual surface
+        Vec3::new(self.alpha * normal.x, self.alpha * normal.y, normal.z.max(1e-9)).unit_vector()
+    }
+}
+
+// Fraction of light reflected at a smooth boundary between dielectrics, averaged over both
+// polarizations. eta is the refractive index on the far side over the one on the incident side,
+// and cos_i is the cosine between the incident direction and the normal.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+
+    // Total internal reflection
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+
+    0.5 * (r_perpendicular * r_perpendicular + r_parallel * r_parallel)
+}
+
+// Fresnel reflectance of a conductor with complex refractive index eta + i k, per color channel
+pub fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let channel = |eta: f64, k: f64| fresnel_complex(cos_i, Complex::new(eta, k));
+    Color::new(channel(eta.x, k.x), channel(eta.y, k.y), channel(eta.z, k.z))
+}
+
+fn fresnel_complex(cos_i: f64, eta: Complex) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_i = 1.0 - cos_i * cos_i;
+
+    // Snell's law still holds with a complex index, giving a complex transmitted angle
+    let sin2_t = Complex::new(sin2_i, 0.0) / (eta * eta);
+    let cos_t = (Complex::new(1.0, 0.0) - sin2_t).sqrt();
+    let cos_i = Complex::new(cos_i, 0.0);
+
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+
+    0.5 * (r_perpendicular.norm2() + r_parallel.norm2())
+}
+
+#[derive(Copy, Clone)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self {
+            re,
+            im,
+        }
+    }
+
+    fn norm2(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root, with a non-negative real part
+    fn sqrt(self) -> Self {
+        let n = self.norm2().sqrt();
+        if n == 0.0 {
+            return Self::new(0.0, 0.0);
+        }
+
+        let re = (0.5 * (n + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (n - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let scale = 1.0 / rhs.norm2();
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) * scale,
+            (self.im * rhs.re - self.re * rhs.im) * scale,
+        )
+    }
+}
@@ -0,0 +1,254 @@
+use crate::hittable::HittableList;
+use crate::image;
+use crate::material::{Dialetric, DiffuseLight, Lambertian, Material, Metal, Principled};
+use crate::mesh::{MeshData, MeshError, TriangleMesh};
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::vec3::{Color, Vec3};
+use std::path::Path;
+use std::sync::Arc;
+
+// Loads every object of a Wavefront OBJ file as a TriangleMesh. Materials from the referenced
+// .mtl files are mapped onto the closest built-in material, or the principled material when they
+// use the PBR extension; objects without one use default_material.
+pub fn load_obj(path: &Path, default_material: Arc<dyn Material + Send>) -> Result<HittableList, MeshError> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+
+    let (models, materials) = tobj::load_obj(path, &options).map_err(|err| match err {
+        tobj::LoadError::OpenFileFailed | tobj::LoadError::ReadError => {
+            MeshError::Io(std::io::Error::other(err.to_string()))
+        }
+        err => MeshError::parse(None, err.to_string()),
+    })?;
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    // A missing or broken .mtl file is not fatal, the default material is used instead
+    let materials: Vec<Arc<dyn Material + Send>> = materials.unwrap_or_default().iter()
+        .map(|material| convert_material(material, base_dir))
+        .collect::<Result<_, _>>()?;
+
+    let mut list = HittableList { objects: vec![] };
+
+    for model in models {
+        let mesh = model.mesh;
+
+        if mesh.indices.is_empty() {
+            continue;
+        }
+
+        let positions: Vec<Vec3> = mesh.positions.chunks_exact(3)
+            .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+
+        let normals = if mesh.normals.len() == mesh.positions.len() {
+            mesh.normals.chunks_exact(3).map(|n| Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64)).collect()
+        } else {
+            vec![]
+        };
+
+        let uvs = if mesh.texcoords.len() / 2 == positions.len() {
+            mesh.texcoords.chunks_exact(2).map(|t| [t[0] as f64, t[1] as f64]).collect()
+        } else {
+            vec![]
+        };
+
+        let indices: Vec<[u32; 3]> = mesh.indices.chunks_exact(3).map(|i| [i[0], i[1], i[2]]).collect();
+        if indices.iter().flatten().any(|&i| i as usize >= positions.len()) {
+            return Err(MeshError::parse(None, format!("face of `{}` references a missing vertex", model.name)));
+        }
+
+        let material = mesh.material_id
+            .and_then(|id| materials.get(id))
+            .cloned()
+            .unwrap_or_else(|| Arc::clone(&default_material));
+
+        list.add(TriangleMesh::new(Arc::new(MeshData { positions, normals, uvs, colors: vec![], indices, material })));
+    }
+
+    Ok(list)
+}
+
+fn to_color(c: [f32; 3]) -> Color {
+    Color::new(c[0] as f64, c[1] as f64, c[2] as f64)
+}
+
+fn parse_color(value: &str) -> Option<Color> {
+    let c: Vec<f64> = value.split_whitespace().filter_map(|c| c.parse().ok()).collect();
+    match c[..] {
+        [r, g, b] => Some(Color::new(r, g, b)),
+        [gray] => Some(Color::splat(gray)),
+        _ => None,
+    }
+}
+
+// Keys of the PBR extension to MTL written by Blender and other tools
+const PBR_KEYS: [&str; 5] = ["Pr", "Pm", "Ps", "Pc", "Pcr"];
+
+fn convert_material(material: &tobj::Material, base_dir: &Path) -> Result<Arc<dyn Material + Send>, MeshError> {
+    if PBR_KEYS.iter().any(|key| material.unknown_param.contains_key(*key)) {
+        return convert_pbr_material(material, base_dir);
+    }
+
+    // Emission isn't part of the original MTL spec so tobj leaves it with the unknown parameters
+    if let Some(emit) = material.unknown_param.get("Ke").and_then(|ke| parse_color(ke)) {
+        if emit.length_squared() > 0.0 {
+            return Ok(Arc::new(DiffuseLight::new(emit)));
+        }
+    }
+
+    let diffuse = material.diffuse.map_or(Color::splat(0.8), to_color);
+    let specular = material.specular.map_or(Color::ZERO, to_color);
+
+    // Illumination models 4, 6, 7 and 9 describe glass, as does any partial transparency
+    let transparent = matches!(material.illumination_model, Some(4 | 6 | 7 | 9))
+        || material.dissolve.is_some_and(|d| d < 1.0);
+    if transparent {
+        return Ok(Arc::new(Dialetric::new(material.optical_density.map_or(1.5, |ni| ni as f64))));
+    }
+
+    // Models 3 and 5 add raytraced reflections on top of the specular highlight
+    if matches!(material.illumination_model, Some(3 | 5)) {
+        let albedo = if specular.length_squared() > 0.0 { specular } else { diffuse };
+        // Approximates the roughness implied by a Phong exponent
+        let fuzz = material.shininess.map_or(0.0, |ns| (2.0 / (ns as f64 + 2.0)).sqrt());
+        return Ok(Arc::new(Metal::new(albedo, fuzz)));
+    }
+
+    Ok(Arc::new(Lambertian::from_texture(diffuse_texture(material, base_dir, diffuse)?)))
+}
+
+fn convert_pbr_material(material: &tobj::Material, base_dir: &Path) -> Result<Arc<dyn Material + Send>, MeshError> {
+    let diffuse = material.diffuse.map_or(Color::splat(0.8), to_color);
+    let mut converted = Principled::from_texture(diffuse_texture(material, base_dir, diffuse)?);
+
+    let parameters = [
+        (&mut converted.roughness, "Pr"),
+        (&mut converted.metallic, "Pm"),
+        (&mut converted.sheen, "Ps"),
+        (&mut converted.clearcoat, "Pc"),
+        (&mut converted.clearcoat_roughness, "Pcr"),
+        (&mut converted.emission, "Ke"),
+    ];
+    for (parameter, key) in parameters {
+        if let Some(value) = material.unknown_param.get(key).and_then(|value| parse_color(value)) {
+            *parameter = Arc::new(SolidColor::new(value));
+        }
+    }
+
+    // Dissolve is the opacity, and what isn't opaque is taken as transmitted
+    if let Some(dissolve) = material.dissolve {
+        converted.transmission = Arc::new(SolidColor::new(Color::splat(1.0 - dissolve as f64)));
+    }
+    if let Some(ni) = material.optical_density {
+        converted.refraction_index = ni as f64;
+    }
+
+    Ok(Arc::new(converted))
+}
+
+fn diffuse_texture(material: &tobj::Material, base_dir: &Path, diffuse: Color) -> Result<Arc<dyn Texture>, MeshError> {
+    Ok(match &material.diffuse_texture {
+        Some(file) => {
+            let path = base_dir.join(file);
+            let image = image::load(&path).map_err(|err| {
+                MeshError::Io(std::io::Error::other(format!("cannot load texture {}: {err}", path.display())))
+            })?;
+            Arc::new(ImageTexture::new(Arc::new(image)))
+        }
+        None => Arc::new(SolidColor::new(diffuse)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::interval::Interval;
+    use crate::ray::Ray;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Writes files into a directory of their own, so .mtl references resolve
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("raytracer-obj-{test}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn load(test: &str, files: &[(&str, &str)]) -> Result<HittableList, MeshError> {
+        let dir = write_files(test, files);
+        let result = load_obj(&dir.join(files[0].0), Arc::new(Lambertian::new(Color::splat(0.5))));
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    const SQUARE: &str = "
+        v -1 -1 0
+        v 1 -1 0
+        v 1 1 0
+        v -1 1 0
+        vn 0 0 1
+        f 1//1 2//1 3//1 4//1
+    ";
+
+    fn hit_square(list: &HittableList) -> Option<crate::hittable::HitRecord> {
+        list.hit(&Ray::new(Vec3::new(0.5, 0.25, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0), Interval::new(0.001, f64::INFINITY))
+    }
+
+    #[test]
+    fn loads_and_triangulates_faces() {
+        let list = load("square", &[("square.obj", SQUARE)]).unwrap();
+        assert_eq!(list.objects.len(), 1);
+
+        let hit = hit_square(&list).expect("ray should hit the square");
+        assert!((hit.t - 5.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+        let bbox = list.bounding_box();
+        assert_eq!((bbox.x.min, bbox.x.max, bbox.y.min, bbox.y.max), (-1.0, 1.0, -1.0, 1.0));
+    }
+
+    #[test]
+    fn maps_emissive_mtl_materials() {
+        let obj = format!("mtllib lamp.mtl\nusemtl glow\n{SQUARE}");
+        let list = load("lamp", &[("lamp.obj", &obj), ("lamp.mtl", "newmtl glow\nKd 0.8 0.8 0.8\nKe 4 3 2\n")]).unwrap();
+
+        let hit = hit_square(&list).unwrap();
+        assert!((hit.material.emitted(&hit) - Color::new(4.0, 3.0, 2.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn missing_mtl_falls_back_to_default_material() {
+        let obj = format!("mtllib missing.mtl\nusemtl glow\n{SQUARE}");
+        let list = load("no-mtl", &[("no-mtl.obj", &obj)]).unwrap();
+
+        let hit = hit_square(&list).unwrap();
+        assert_eq!(hit.material.emitted(&hit).length(), 0.0);
+    }
+
+    #[test]
+    fn rejects_missing_vertices() {
+        let err = load("bad-index", &[("bad-index.obj", "v 0 0 0\nv 1 0 0\nf 1 2 7\n")]).err().expect("expected an error");
+        assert!(matches!(err, MeshError::Parse { .. }), "{err}");
+    }
+
+    #[test]
+    fn rejects_malformed_faces() {
+        let err = load("bad-face", &[("bad-face.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 two 3\n")]).err().expect("expected an error");
+        assert!(matches!(err, MeshError::Parse { .. }), "{err}");
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let err = load_obj(Path::new("does/not/exist.obj"), Arc::new(Lambertian::new(Color::ONE))).err().expect("expected an error");
+        assert!(matches!(err, MeshError::Io(_)), "{err}");
+    }
+}
@@ -0,0 +1,33 @@
+use crate::vec3::Vec3;
+
+// Orthonormal basis around a unit vector w, for working with directions relative to a surface
+// normal. Built without branching on the normal's direction (Duff et al. 2017).
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: Vec3) -> Self {
+        let sign = 1.0f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+
+        Self {
+            u: Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    // World space direction of local coordinates, with z along w
+    pub fn to_world(&self, local: Vec3) -> Vec3 {
+        local.x * self.u + local.y * self.v + local.z * self.w
+    }
+
+    pub fn to_local(&self, world: Vec3) -> Vec3 {
+        Vec3::new(world.dot(&self.u), world.dot(&self.v), world.dot(&self.w))
+    }
+}
@@ -0,0 +1,129 @@
+use crate::hittable::Hittable;
+use crate::material::random_unit_vector;
+use crate::onb::Onb;
+use crate::rng::random;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+
+// Probability density over directions, per unit solid angle, that directions can also be drawn
+// from. Sampling directions where light is likely to come from and dividing by the density gives
+// the same average with much less noise.
+pub trait Pdf {
+    fn value(&self, direction: Vec3) -> f64;
+
+    fn generate(&self) -> Vec3;
+}
+
+// Every direction equally likely
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        random_unit_vector()
+    }
+}
+
+// Directions around a normal in proportion to the cosine of their angle with it, matching how much
+// light a diffuse surface receives from each direction
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        Self {
+            uvw: Onb::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine = direction.unit_vector().dot(&self.uvw.w);
+        f64::max(0.0, cosine / PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.to_world(random_cosine_direction())
+    }
+}
+
+// Directions from origin towards points on objects, such as lights
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Vec3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Vec3) -> Self {
+        Self {
+            objects,
+            origin,
+        }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.objects.pdf_value(self.origin, direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.objects.random(self.origin)
+    }
+}
+
+// Borrowed densities, for mixing in ones owned elsewhere
+impl<P: Pdf + ?Sized> Pdf for &P {
+    fn value(&self, direction: Vec3) -> f64 {
+        (**self).value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        (**self).generate()
+    }
+}
+
+// Picks one of two densities with equal chance
+pub struct MixturePdf<'a> {
+    p: [Box<dyn Pdf + 'a>; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: Box<dyn Pdf + 'a>, p1: Box<dyn Pdf + 'a>) -> Self {
+        Self {
+            p: [p0, p1],
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        if random::<f64>() < 0.5 {
+            self.p[0].generate()
+        } else {
+            self.p[1].generate()
+        }
+    }
+}
+
+// Direction in the hemisphere around +z, distributed by the cosine of its angle with z
+pub fn random_cosine_direction() -> Vec3 {
+    let r1: f64 = random();
+    let r2: f64 = random();
+
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+
+    Vec3::new(x, y, z)
+}
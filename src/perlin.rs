@@ -0,0 +1,131 @@
+use crate::vec3::Vec3;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+// Gradient (Perlin) noise over 3D space. Tables are generated from a seed so the same seed always
+// produces the same pattern.
+pub struct Perlin {
+    random_vectors: [Vec3; POINT_COUNT],
+    perm_x: [usize; POINT_COUNT],
+    perm_y: [usize; POINT_COUNT],
+    perm_z: [usize; POINT_COUNT],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+
+        let mut random_vectors = [Vec3::ZERO; POINT_COUNT];
+        for vector in random_vectors.iter_mut() {
+            // Rejection sample so the gradients are uniformly distributed over directions
+            *vector = loop {
+                let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                let lensq = p.length_squared();
+                if 1e-160 < lensq && lensq <= 1.0 {
+                    break p / lensq.sqrt();
+                }
+            };
+        }
+
+        Self {
+            random_vectors,
+            perm_x: Self::generate_perm(&mut rng),
+            perm_y: Self::generate_perm(&mut rng),
+            perm_z: Self::generate_perm(&mut rng),
+        }
+    }
+
+    fn generate_perm(rng: &mut SmallRng) -> [usize; POINT_COUNT] {
+        let mut perm = [0; POINT_COUNT];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = i;
+        }
+        perm.shuffle(rng);
+        perm
+    }
+
+    // Smooth noise in roughly [-1, 1]
+    pub fn noise(&self, point: &Vec3) -> f64 {
+        let u = point.x - point.x.floor();
+        let v = point.y - point.y.floor();
+        let w = point.z - point.z.floor();
+
+        let i = point.x.floor() as i64;
+        let j = point.y.floor() as i64;
+        let k = point.z.floor() as i64;
+
+        let mut c = [[[Vec3::ZERO; 2]; 2]; 2];
+
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+                    *corner = self.random_vectors[index];
+                }
+            }
+        }
+
+        Self::perlin_interp(&c, u, v, w)
+    }
+
+    fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        // Hermite smoothing hides the grid lines of plain trilinear interpolation
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * corner.dot(&weight);
+                }
+            }
+        }
+
+        accum
+    }
+
+    // Fractal Brownian motion: sum of octaves of noise, each at double the frequency and half the
+    // amplitude of the previous one
+    pub fn fbm(&self, point: &Vec3, octaves: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut temp = *point;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp);
+            weight *= 0.5;
+            temp *= 2.0;
+        }
+
+        accum
+    }
+
+    // Like fbm but summing the absolute value of each octave, which gives sharp creases
+    pub fn turbulence(&self, point: &Vec3, octaves: usize) -> f64 {
+        let mut accum = 0.0;
+        let mut temp = *point;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp).abs();
+            weight *= 0.5;
+            temp *= 2.0;
+        }
+
+        accum
+    }
+}
@@ -0,0 +1,469 @@
+use crate::material::Material;
+use crate::mesh::{MeshData, MeshError};
+use crate::vec3::{Color, Vec3};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+// Reads a Stanford PLY file, ASCII or binary. Vertex colors are used to tint the albedo of material.
+pub fn load_ply(path: &Path, material: Arc<dyn Material + Send>) -> Result<MeshData, MeshError> {
+    read_ply(&mut BufReader::new(File::open(path)?), material)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // Largest value of integer types, which store colors in [0, max]
+    fn max(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar { name: String, ty: Scalar },
+    List { name: String, count: Scalar, item: Scalar },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } | Property::List { name, .. } => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn scalar(&self, names: &[&str]) -> Option<(usize, Scalar)> {
+        self.properties.iter().position(|p| names.contains(&p.name())).and_then(|i| match self.properties[i] {
+            Property::Scalar { ty, .. } => Some((i, ty)),
+            Property::List { .. } => None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+// Source of element values, which in ASCII files are whitespace separated with one element per line
+struct Body<'a> {
+    input: &'a mut dyn BufRead,
+    format: Format,
+    line: usize,
+    tokens: Vec<String>,
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, MeshError> {
+        if self.format == Format::Ascii {
+            return self.read_ascii(ty);
+        }
+
+        let mut bytes = [0u8; 8];
+        let bytes = &mut bytes[..ty.size()];
+        self.input.read_exact(bytes).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => MeshError::parse(None, "unexpected end of file"),
+            _ => MeshError::Io(err),
+        })?;
+
+        if self.format == Format::BinaryBigEndian {
+            bytes.reverse();
+        }
+
+        Ok(match ty {
+            Scalar::I8 => bytes[0] as i8 as f64,
+            Scalar::U8 => bytes[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+        })
+    }
+
+    fn read_ascii(&mut self, ty: Scalar) -> Result<f64, MeshError> {
+        while self.tokens.is_empty() {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(MeshError::parse(Some(self.line), "unexpected end of file"));
+            }
+            self.line += 1;
+            self.tokens = line.split_whitespace().rev().map(str::to_string).collect();
+        }
+
+        let token = self.tokens.pop().unwrap();
+        let value: f64 = token.parse()
+            .map_err(|_| MeshError::parse(Some(self.line), format!("invalid number `{token}`")))?;
+
+        if !matches!(ty, Scalar::F32 | Scalar::F64) && value.fract() != 0.0 {
+            return Err(MeshError::parse(Some(self.line), format!("expected an integer, got `{token}`")));
+        }
+
+        Ok(value)
+    }
+
+    // Element indices must be whole and fit in the vertex buffer
+    fn read_index(&mut self, ty: Scalar, vertex_count: usize) -> Result<u32, MeshError> {
+        let index = self.read(ty)?;
+        if index < 0.0 || index >= vertex_count as f64 {
+            return Err(MeshError::parse(self.location(), format!("vertex index {index} out of range")));
+        }
+        Ok(index as u32)
+    }
+
+    fn location(&self) -> Option<usize> {
+        (self.format == Format::Ascii).then_some(self.line)
+    }
+}
+
+fn read_header(input: &mut dyn BufRead) -> Result<(Format, Vec<Element>, usize), MeshError> {
+    let mut line_number = 0;
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(MeshError::parse(Some(line_number), "unexpected end of header"));
+        }
+        line_number += 1;
+
+        let error = |message: String| MeshError::parse(Some(line_number), message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        if line_number == 1 {
+            if words != ["ply"] {
+                return Err(error("not a PLY file".to_string()));
+            }
+            continue;
+        }
+
+        match words[..] {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, _version] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(format!("unknown format `{name}`"))),
+                });
+            }
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| error(format!("invalid element count `{count}`")))?;
+                elements.push(Element { name: name.to_string(), count, properties: vec![] });
+            }
+            ["property", "list", count, item, name] => {
+                let count = Scalar::parse(count).ok_or_else(|| error(format!("unknown type `{count}`")))?;
+                let item = Scalar::parse(item).ok_or_else(|| error(format!("unknown type `{item}`")))?;
+                let element = elements.last_mut().ok_or_else(|| error("property outside of an element".to_string()))?;
+                element.properties.push(Property::List { name: name.to_string(), count, item });
+            }
+            ["property", ty, name] => {
+                let ty = Scalar::parse(ty).ok_or_else(|| error(format!("unknown type `{ty}`")))?;
+                let element = elements.last_mut().ok_or_else(|| error("property outside of an element".to_string()))?;
+                element.properties.push(Property::Scalar { name: name.to_string(), ty });
+            }
+            ["end_header"] => break,
+            _ => return Err(error(format!("unexpected header line `{}`", line.trim_end()))),
+        }
+    }
+
+    let format = format.ok_or_else(|| MeshError::parse(Some(line_number), "header has no format line"))?;
+
+    Ok((format, elements, line_number))
+}
+
+pub fn read_ply(input: &mut dyn BufRead, material: Arc<dyn Material + Send>) -> Result<MeshData, MeshError> {
+    let (format, elements, header_lines) = read_header(input)?;
+
+    let mut body = Body { input, format, line: header_lines, tokens: vec![] };
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+
+    // Faces reference vertices by index, so they have to come after them
+    let mut vertex_count = None;
+
+    for element in &elements {
+        let mut values = vec![0.0; element.properties.len()];
+
+        match element.name.as_str() {
+            "vertex" => {
+                let missing = |axis| MeshError::parse(None, format!("vertex element has no `{axis}` property"));
+                let x = element.scalar(&["x"]).ok_or_else(|| missing("x"))?.0;
+                let y = element.scalar(&["y"]).ok_or_else(|| missing("y"))?.0;
+                let z = element.scalar(&["z"]).ok_or_else(|| missing("z"))?.0;
+
+                let normal = element.scalar(&["nx"]).zip(element.scalar(&["ny"])).zip(element.scalar(&["nz"]));
+                let uv = element.scalar(&["u", "s", "texture_u"]).zip(element.scalar(&["v", "t", "texture_v"]));
+                let color = element.scalar(&["red", "r"])
+                    .zip(element.scalar(&["green", "g"]))
+                    .zip(element.scalar(&["blue", "b"]));
+
+                // The count comes from the file, don't trust it for large allocations
+                positions.reserve(element.count.min(1 << 20));
+
+                for _ in 0..element.count {
+                    for (value, property) in values.iter_mut().zip(&element.properties) {
+                        *value = match *property {
+                            Property::Scalar { ty, .. } => body.read(ty)?,
+                            Property::List { count, item, .. } => {
+                                skip_list(&mut body, count, item)?;
+                                0.0
+                            }
+                        };
+                    }
+
+                    positions.push(Vec3::new(values[x], values[y], values[z]));
+
+                    if let Some((((nx, _), (ny, _)), (nz, _))) = normal {
+                        normals.push(Vec3::new(values[nx], values[ny], values[nz]));
+                    }
+                    if let Some(((u, _), (v, _))) = uv {
+                        uvs.push([values[u], values[v]]);
+                    }
+                    if let Some((((r, ty), (g, _)), (b, _))) = color {
+                        colors.push(to_linear(Color::new(values[r], values[g], values[b]), ty));
+                    }
+                }
+
+                vertex_count = Some(element.count);
+            }
+            "face" => {
+                let list = element.properties.iter().position(|p| {
+                    matches!(p, Property::List { .. }) && matches!(p.name(), "vertex_indices" | "vertex_index")
+                });
+                let list = list.ok_or_else(|| MeshError::parse(None, "face element has no `vertex_indices` list"))?;
+                let vertex_count = vertex_count.ok_or_else(|| MeshError::parse(None, "faces are declared before vertices"))?;
+
+                indices.reserve(element.count.min(1 << 20));
+
+                for _ in 0..element.count {
+                    for (i, property) in element.properties.iter().enumerate() {
+                        match *property {
+                            Property::Scalar { ty, .. } => {
+                                body.read(ty)?;
+                            }
+                            Property::List { count, item, .. } if i == list => {
+                                let count = body.read(count)?;
+                                if count < 3.0 {
+                                    return Err(MeshError::parse(body.location(), format!("face with {count} vertices")));
+                                }
+
+                                // Polygons are split into a fan of triangles around their first vertex
+                                let first = body.read_index(item, vertex_count)?;
+                                let mut previous = body.read_index(item, vertex_count)?;
+                                for _ in 2..count as usize {
+                                    let next = body.read_index(item, vertex_count)?;
+                                    indices.push([first, previous, next]);
+                                    previous = next;
+                                }
+                            }
+                            Property::List { count, item, .. } => skip_list(&mut body, count, item)?,
+                        }
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match *property {
+                            Property::Scalar { ty, .. } => {
+                                body.read(ty)?;
+                            }
+                            Property::List { count, item, .. } => skip_list(&mut body, count, item)?,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if positions.is_empty() {
+        return Err(MeshError::parse(None, "file contains no vertices"));
+    }
+
+    Ok(MeshData {
+        positions,
+        normals,
+        uvs,
+        colors,
+        indices,
+        material,
+    })
+}
+
+fn skip_list(body: &mut Body, count: Scalar, item: Scalar) -> Result<(), MeshError> {
+    let count = body.read(count)?;
+    if count < 0.0 {
+        return Err(MeshError::parse(body.location(), format!("negative list length {count}")));
+    }
+    for _ in 0..count as usize {
+        body.read(item)?;
+    }
+    Ok(())
+}
+
+// Integer colors are gamma encoded like 8 bit images, floating point colors are taken as linear
+fn to_linear(color: Color, ty: Scalar) -> Color {
+    match ty {
+        Scalar::F32 | Scalar::F64 => color,
+        _ => {
+            let c = color / ty.max();
+            c * c
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn read(bytes: &[u8]) -> Result<MeshData, MeshError> {
+        read_ply(&mut &bytes[..], Arc::new(Lambertian::new(Color::splat(0.5))))
+    }
+
+    fn parse_error(bytes: &[u8]) -> (Option<usize>, String) {
+        match read(bytes) {
+            Err(MeshError::Parse { line, message }) => (line, message),
+            Err(err) => panic!("expected a parse error, got `{err}`"),
+            Ok(_) => panic!("expected the file to be rejected"),
+        }
+    }
+
+    fn coords(points: &[Vec3]) -> Vec<[f64; 3]> {
+        points.iter().map(|p| [p.x, p.y, p.z]).collect()
+    }
+
+    const HEADER: &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    #[test]
+    fn reads_ascii_and_splits_polygons() {
+        let source = format!("ply\nformat ascii 1.0\ncomment a quad\n{HEADER}0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n");
+        let mesh = read(source.as_bytes()).unwrap();
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(coords(&mesh.positions)[2], [1.0, 1.0, 0.0]);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.normals.is_empty() && mesh.uvs.is_empty() && mesh.colors.is_empty());
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let ascii = format!("ply\nformat ascii 1.0\n{HEADER}0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n");
+
+        for (name, to_bytes) in [
+            ("binary_little_endian", f32::to_le_bytes as fn(f32) -> [u8; 4]),
+            ("binary_big_endian", f32::to_be_bytes),
+        ] {
+            let mut binary = format!("ply\nformat {name} 1.0\n{HEADER}").into_bytes();
+            for p in [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]] {
+                binary.extend(p.iter().flat_map(|&c: &f32| to_bytes(c)));
+            }
+            binary.push(4);
+            for i in 0..4i32 {
+                binary.extend(if name.ends_with("little_endian") { i.to_le_bytes() } else { i.to_be_bytes() });
+            }
+
+            let (a, b) = (read(ascii.as_bytes()).unwrap(), read(&binary).unwrap());
+            assert_eq!(coords(&a.positions), coords(&b.positions), "{name}");
+            assert_eq!(a.indices, b.indices, "{name}");
+        }
+    }
+
+    #[test]
+    fn reads_normals_uvs_and_colors() {
+        let source = "ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            property float s\nproperty float t\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar uint vertex_index\nend_header\n\
+            0 0 0 0 0 1 0 0 255 0 0\n1 0 0 0 0 1 1 0 0 255 0\n0 1 0 0 0 1 0 1 0 0 0\n3 0 1 2\n";
+        let mesh = read(source.as_bytes()).unwrap();
+
+        assert_eq!(coords(&mesh.normals), vec![[0.0, 0.0, 1.0]; 3]);
+        assert_eq!(mesh.uvs, vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        assert_eq!(coords(&mesh.colors), vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert_eq!(parse_error(b"obj\n").1, "not a PLY file");
+        assert_eq!(parse_error(b"ply\nformat ascii 1.0\n"), (Some(2), "unexpected end of header".to_string()));
+        assert_eq!(parse_error(b"ply\nformat packed 1.0\nend_header\n"), (Some(2), "unknown format `packed`".to_string()));
+        assert_eq!(parse_error(b"ply\nelement vertex 0\nend_header\n").1, "header has no format line");
+        assert_eq!(parse_error(b"ply\nformat ascii 1.0\nproperty float x\nend_header\n").1, "property outside of an element");
+        assert_eq!(parse_error(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n").1, "unknown type `half`");
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        let quad = |body: &str| format!("ply\nformat ascii 1.0\n{HEADER}{body}");
+
+        assert_eq!(parse_error(quad("0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 9\n").as_bytes()), (Some(14), "vertex index 9 out of range".to_string()));
+        assert_eq!(parse_error(quad("0 0 0\n1 0 0\n1 1 0\n0 1 0\n2 0 1\n").as_bytes()), (Some(14), "face with 2 vertices".to_string()));
+        assert_eq!(parse_error(quad("0 0 zero\n").as_bytes()), (Some(10), "invalid number `zero`".to_string()));
+        assert_eq!(parse_error(quad("0 0 0\n1 0 0\n").as_bytes()).1, "unexpected end of file");
+
+        let binary = format!("ply\nformat binary_little_endian 1.0\n{HEADER}\0\0\0\0");
+        assert_eq!(parse_error(binary.as_bytes()), (None, "unexpected end of file".to_string()));
+    }
+}
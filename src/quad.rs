@@ -0,0 +1,222 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable, HittableList};
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rng::random;
+use crate::vec3::Vec3;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Region of the plane spanned by u and v that belongs to the primitive, in terms of the plane
+// coordinates (alpha, beta) of a point origin + alpha * u + beta * v
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum PlanarShape {
+    // 0 <= alpha, beta <= 1
+    Parallelogram,
+    // alpha, beta >= 0 and alpha + beta <= 1
+    Triangle,
+    // alpha^2 + beta^2 <= 1, origin is the center and u, v the radii
+    Disk,
+    // inner^2 <= alpha^2 + beta^2 <= 1, inner being the ratio of the hole to the outer radius
+    Annulus { inner: f64 },
+}
+
+#[derive(Clone)]
+pub struct Quad {
+    origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    // Cached n / (n . n) used to find the plane coordinates of hit points
+    w: Vec3,
+    normal: Vec3,
+    // Plane equation constant, normal . p = d for points p on the plane
+    d: f64,
+    shape: PlanarShape,
+    area: f64,
+    material: Arc<dyn Material + Send>,
+    bbox: AABB,
+}
+
+impl Quad {
+    // Parallelogram with one corner at origin and the two adjacent edges u and v
+    pub fn new(origin: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material + Send>) -> Self {
+        Self::with_shape(origin, u, v, PlanarShape::Parallelogram, material)
+    }
+
+    // Triangle with vertices origin, origin + u and origin + v
+    pub fn triangle(origin: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material + Send>) -> Self {
+        Self::with_shape(origin, u, v, PlanarShape::Triangle, material)
+    }
+
+    // Disk (or ellipse when u and v differ in length) around center
+    pub fn disk(center: Vec3, u: Vec3, v: Vec3, material: Arc<dyn Material + Send>) -> Self {
+        Self::with_shape(center, u, v, PlanarShape::Disk, material)
+    }
+
+    // Ring around center; inner is the size of the hole relative to the outer radius
+    pub fn annulus(center: Vec3, u: Vec3, v: Vec3, inner: f64, material: Arc<dyn Material + Send>) -> Self {
+        Self::with_shape(center, u, v, PlanarShape::Annulus { inner: inner.clamp(0.0, 1.0) }, material)
+    }
+
+    pub fn with_shape(origin: Vec3, u: Vec3, v: Vec3, shape: PlanarShape, material: Arc<dyn Material + Send>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+
+        let bbox = match shape {
+            PlanarShape::Parallelogram | PlanarShape::Triangle => AABB::from_boxes(
+                &AABB::from_points(origin, origin + u + v),
+                &AABB::from_points(origin + u, origin + v),
+            ),
+            PlanarShape::Disk | PlanarShape::Annulus { .. } => AABB::from_boxes(
+                &AABB::from_points(origin - u - v, origin + u + v),
+                &AABB::from_points(origin + u - v, origin - u + v),
+            ),
+        };
+
+        // Area of the parallelogram spanned by u and v, scaled to the part the shape covers
+        let area = n.length() * match shape {
+            PlanarShape::Parallelogram => 1.0,
+            PlanarShape::Triangle => 0.5,
+            PlanarShape::Disk => PI,
+            PlanarShape::Annulus { inner } => PI * (1.0 - inner * inner),
+        };
+
+        Self {
+            origin,
+            u,
+            v,
+            w: n / n.dot(&n),
+            normal,
+            d: normal.dot(&origin),
+            shape,
+            area,
+            material,
+            bbox: bbox.pad_to_minimums(0.0001),
+        }
+    }
+
+    // Returns the surface (u, v) coordinates if the plane coordinates lie inside the shape
+    fn is_interior(&self, alpha: f64, beta: f64) -> Option<(f64, f64)> {
+        let unit = Interval::new(0.0, 1.0);
+
+        match self.shape {
+            PlanarShape::Parallelogram => {
+                (unit.contains(alpha) && unit.contains(beta)).then_some((alpha, beta))
+            }
+            PlanarShape::Triangle => {
+                (alpha >= 0.0 && beta >= 0.0 && alpha + beta <= 1.0).then_some((alpha, beta))
+            }
+            PlanarShape::Disk => {
+                (alpha * alpha + beta * beta <= 1.0).then_some((0.5 * (alpha + 1.0), 0.5 * (beta + 1.0)))
+            }
+            PlanarShape::Annulus { inner } => {
+                let r2 = alpha * alpha + beta * beta;
+                (inner * inner <= r2 && r2 <= 1.0).then_some((0.5 * (alpha + 1.0), 0.5 * (beta + 1.0)))
+            }
+        }
+    }
+
+    // Uniformly distributed point on the shape
+    fn random_point(&self) -> Vec3 {
+        let (alpha, beta) = match self.shape {
+            PlanarShape::Parallelogram => (random(), random()),
+            PlanarShape::Triangle => {
+                // Points beyond the diagonal are mirrored back onto the triangle
+                let (alpha, beta): (f64, f64) = (random(), random());
+                if alpha + beta > 1.0 { (1.0 - alpha, 1.0 - beta) } else { (alpha, beta) }
+            }
+            PlanarShape::Disk | PlanarShape::Annulus { .. } => {
+                let inner = match self.shape {
+                    PlanarShape::Annulus { inner } => inner,
+                    _ => 0.0,
+                };
+                let radius = (inner * inner + random::<f64>() * (1.0 - inner * inner)).sqrt();
+                let phi = 2.0 * PI * random::<f64>();
+                (radius * phi.cos(), radius * phi.sin())
+            }
+        };
+
+        self.origin + alpha * self.u + beta * self.v
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let denom = self.normal.dot(&ray.direction);
+
+        // No hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin)) / denom;
+        if !ray_t.contains(t) {
+            return None;
+        }
+
+        // Determine if the hit point lies within the shape using its plane coordinates
+        let intersection = ray.at(t);
+        let planar_hitpt_vector = intersection - self.origin;
+        let alpha = self.w.dot(&planar_hitpt_vector.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hitpt_vector));
+
+        let (u, v) = self.is_interior(alpha, beta)?;
+
+        let mut hr = HitRecord {
+            point: intersection,
+            normal: Vec3::ZERO,
+            material: Arc::clone(&self.material),
+            t,
+            u,
+            v,
+            color: None,
+            front_face: false,
+        };
+        hr.set_face_normal(ray, self.normal);
+
+        Some(hr)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    // Points are sampled uniformly over the area, so the density per solid angle grows with the
+    // squared distance and as the shape turns edge on
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        let Some(hit) = self.hit(&Ray::new(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY)) else {
+            return 0.0;
+        };
+
+        let distance_squared = hit.t * hit.t * direction.length_squared();
+        let cosine = (direction.dot(&self.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        self.random_point() - origin
+    }
+}
+
+// Returns the six sides of the axis aligned box with opposite corners a and b
+pub fn make_box(a: Vec3, b: Vec3, material: Arc<dyn Material + Send>) -> HittableList {
+    let mut sides = HittableList { objects: vec![] };
+
+    let min = Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = Vec3::new(max.x - min.x, 0., 0.);
+    let dy = Vec3::new(0., max.y - min.y, 0.);
+    let dz = Vec3::new(0., 0., max.z - min.z);
+
+    sides.add(Quad::new(Vec3::new(min.x, min.y, max.z), dx, dy, Arc::clone(&material))); // front
+    sides.add(Quad::new(Vec3::new(max.x, min.y, max.z), -dz, dy, Arc::clone(&material))); // right
+    sides.add(Quad::new(Vec3::new(max.x, min.y, min.z), -dx, dy, Arc::clone(&material))); // back
+    sides.add(Quad::new(Vec3::new(min.x, min.y, min.z), dz, dy, Arc::clone(&material))); // left
+    sides.add(Quad::new(Vec3::new(min.x, max.y, max.z), dx, -dz, Arc::clone(&material))); // top
+    sides.add(Quad::new(Vec3::new(min.x, min.y, min.z), dx, dz, material)); // bottom
+
+    sides
+}
@@ -0,0 +1,107 @@
+use crate::matrix::Mat4;
+use crate::vec3::Vec3;
+use std::ops::Mul;
+
+// Unit quaternion representing a rotation, which interpolates smoothly unlike Euler angles
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub const IDENTITY: Self = Self { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    // Counterclockwise rotation by degrees around axis, matching Mat4::rotation
+    pub fn from_axis_angle(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = (0.5 * degrees.to_radians()).sin_cos();
+        Self { w: cos, x: sin * a.x, y: sin * a.y, z: sin * a.z }
+    }
+
+    // Rotation around x, then y, then z, in degrees
+    pub fn from_euler(degrees: [f64; 3]) -> Self {
+        Self::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), degrees[2])
+            * Self::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), degrees[1])
+            * Self::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), degrees[0])
+    }
+
+    pub fn dot(&self, other: &Quat) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalized(&self) -> Self {
+        let length = self.dot(self).sqrt();
+        Self { w: self.w / length, x: self.x / length, y: self.y / length, z: self.z / length }
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    // Angle in radians of the rotation taking self to other
+    pub fn angle_to(&self, other: &Quat) -> f64 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    // Spherical linear interpolation, rotating at constant speed along the shortest arc
+    pub fn slerp(&self, other: &Quat, t: f64) -> Self {
+        // q and -q are the same rotation, pick the one on the near side
+        let mut cos = self.dot(other);
+        let other = if cos < 0.0 {
+            cos = -cos;
+            Self { w: -other.w, x: -other.x, y: -other.y, z: -other.z }
+        } else {
+            *other
+        };
+
+        // Nearly identical rotations would divide by almost zero, a straight blend is accurate there
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Self {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        }.normalized()
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        let Quat { w, x, y, z } = *self;
+
+        Mat4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+// Composes rotations, with rhs applied first
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::vec3::Vec3;
 
+#[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
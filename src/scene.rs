@@ -0,0 +1,1455 @@
+use crate::background::{Background, EnvironmentMap, SKY_HORIZON, SKY_ZENITH};
+use crate::bvh::FlatBvh;
+use crate::camera::{Camera, CameraKeyframe};
+use crate::gltf;
+use crate::hittable::{Hittable, HittableList};
+use crate::image;
+use crate::light::Light;
+use crate::material::{Conductor, Dialetric, DiffuseLight, Isotropic, Lambertian, Material, Metal, MetalKind, Principled, RoughDielectric};
+use crate::matrix::Mat4;
+use crate::mesh;
+use crate::quaternion::Quat;
+use crate::rng::{gen_range, random};
+use crate::sky::Sky;
+use crate::quad::{make_box, Quad};
+use crate::sphere::Sphere;
+use crate::transform::{AnimatedTransform, Keyframe, Transform};
+use crate::texture::{CheckerTexture, ImageTexture, NoisePattern, NoiseTexture, SolidColor, Texture};
+use crate::vec3::{Color, Vec3};
+use crate::volume::{ConstantMedium, Emission, GridMedium};
+use crate::voxel::{self, VoxelGrid};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+
+// Scene files are TOML documents with optional [camera] and [background] tables, named
+// [textures.<name>] and [materials.<name>] tables and an [[objects]] array referencing materials
+// by name:
+//
+//     [camera]
+//     lookfrom = [13.0, 2.0, 3.0]
+//     vfov = 20.0
+//
+//     [[camera.keyframes]]   # moves the camera while the shutter is open, lookfrom and lookat
+//     time = 0.5             # default to the ones above
+//     lookfrom = [13.0, 2.1, 3.0]
+//
+//     [background]
+//     type = "environment"
+//     image = "sky.hdr"   # relative to the scene file
+//     rotation = 90.0
+//
+//     [textures.checker]
+//     type = "checker"
+//     scale = 0.32
+//     even = [0.2, 0.3, 0.1]
+//     odd = [0.9, 0.9, 0.9]
+//
+//     [textures.marble]
+//     type = "noise"
+//     pattern = "marble"   # noise, fbm, turbulence, marble or wood
+//     scale = 4.0
+//     seed = 7
+//
+//     [materials.ground]
+//     type = "lambertian"
+//     texture = "checker"   # or a constant albedo = [0.5, 0.5, 0.5]
+//
+//     [materials.gold]
+//     type = "conductor"
+//     metal = "gold"   # gold, copper, aluminium or silver, or a refractive index eta = [r, g, b]
+//     roughness = 0.3  # and k = [r, g, b]
+//
+//     [materials.frosted]
+//     type = "rough_dielectric"
+//     refraction_index = 1.5
+//     roughness = 0.2
+//
+//     [materials.car_paint]
+//     type = "principled"   # base color from albedo or texture, every other parameter is
+//     albedo = [0.6, 0.05, 0.05]   # a number, a color or a texture name
+//     metallic = 0.0
+//     roughness = "scratches"
+//     specular = 0.5
+//     sheen = 0.0
+//     clearcoat = 1.0
+//     clearcoat_roughness = 0.03
+//     transmission = 0.0
+//     refraction_index = 1.5
+//     emit = [0.0, 0.0, 0.0]
+//
+//     [[objects]]
+//     type = "sphere"
+//     center = [0.0, -1000.0, 0.0]
+//     radius = 1000.0
+//     material = "ground"
+//
+//     [[objects]]
+//     type = "mesh"
+//     file = "teapot.obj"   # .obj, .ply or .stl; the material applies to faces without their own
+//     translate = [0.0, 0.0, 2.0]   # optional on every object, along with rotate and scale
+//     rotate = [0.0, 45.0, 0.0]
+//     scale = 0.5
+//
+//     [[objects]]
+//     type = "box"
+//     min = [-1.0, -0.1, -1.0]
+//     max = [1.0, 0.1, 1.0]
+//     material = "ground"
+//
+//     [[objects.keyframes]]   # moves the object while the shutter is open
+//     time = 0.0              # 0 when the shutter opens, 1 when it closes
+//     rotate = [0.0, 0.0, 0.0]
+//
+//     [[objects.keyframes]]   # rotations take the shortest way, so turns past 180 degrees
+//     time = 1.0              # need keyframes in between
+//     rotate = [0.0, 90.0, 0.0]
+//
+//     [materials.smoke]
+//     type = "isotropic"
+//     albedo = [0.1, 0.1, 0.1]
+//
+//     [[objects]]
+//     type = "sphere"
+//     center = [0.0, 1.0, 0.0]
+//     radius = 1.0
+//     material = "smoke"
+//     density = 0.5   # fills the shape with a medium instead of giving it a surface
+//
+//     [[objects]]
+//     type = "volume"
+//     file = "cloud.vol"   # Mitsuba voxel grid of densities
+//     material = "smoke"
+//     density = 20.0   # multiplies the grid values
+//     temperature = "fire.vol"   # optional grid in kelvin glowing as a blackbody, or a
+//     intensity = 5.0            # constant emit = [r, g, b]
+
+pub struct Scene {
+    pub world: HittableList,
+    // Emissive objects of world the renderer samples directly
+    pub lights: HittableList,
+    pub punctual_lights: Vec<Light>,
+    pub camera: Camera,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: Option<PathBuf>,
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, "{}:{}:", location.line, location.column)?;
+        }
+        if self.path.is_some() || self.location.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDef {
+    camera: Option<Spanned<CameraDef>>,
+    background: Option<Spanned<BackgroundDef>>,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDef>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDef>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDef>>,
+    #[serde(default)]
+    lights: Vec<Spanned<LightDef>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    aspect_ratio: Option<f64>,
+    width: Option<i32>,
+    samples_per_pixel: Option<i32>,
+    max_depth: Option<i32>,
+    vfov: Option<f64>,
+    lookfrom: Option<[f64; 3]>,
+    lookat: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    defocus_angle: Option<f64>,
+    focus_distance: Option<f64>,
+    #[serde(default)]
+    keyframes: Vec<CameraKeyframeDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraKeyframeDef {
+    // 0 when the shutter opens and 1 when it closes
+    time: f64,
+    lookfrom: Option<[f64; 3]>,
+    lookat: Option<[f64; 3]>,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum BackgroundKind {
+    Solid,
+    Gradient,
+    Environment,
+    Sky,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BackgroundDef {
+    #[serde(rename = "type")]
+    kind: BackgroundKind,
+    color: Option<[f64; 3]>,
+    horizon: Option<[f64; 3]>,
+    zenith: Option<[f64; 3]>,
+    image: Option<Spanned<String>>,
+    rotation: Option<f64>,
+    intensity: Option<f64>,
+    // Sun position in degrees for the sky, azimuth 0 being towards -z and 90 towards +x
+    elevation: Option<f64>,
+    azimuth: Option<f64>,
+    turbidity: Option<f64>,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum TextureKind {
+    Solid,
+    Checker,
+    Image,
+    Noise,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum NoisePatternDef {
+    Noise,
+    Fbm,
+    Turbulence,
+    Marble,
+    Wood,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextureDef {
+    #[serde(rename = "type")]
+    kind: TextureKind,
+    color: Option<[f64; 3]>,
+    scale: Option<f64>,
+    even: Option<[f64; 3]>,
+    odd: Option<[f64; 3]>,
+    file: Option<Spanned<String>>,
+    pattern: Option<NoisePatternDef>,
+    seed: Option<u64>,
+    octaves: Option<usize>,
+    low: Option<[f64; 3]>,
+    high: Option<[f64; 3]>,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum MaterialKind {
+    Lambertian,
+    Metal,
+    Dielectric,
+    DiffuseLight,
+    Isotropic,
+    Conductor,
+    RoughDielectric,
+    Principled,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum MetalDef {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+// Every material kind shares one table layout; which fields are required depends on the kind
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDef {
+    #[serde(rename = "type")]
+    kind: MaterialKind,
+    albedo: Option<[f64; 3]>,
+    texture: Option<Spanned<String>>,
+    fuzz: Option<f64>,
+    refraction_index: Option<f64>,
+    emit: Option<ParamDef>,
+    // Conductors take a named metal or the real and imaginary parts of their refractive index
+    metal: Option<MetalDef>,
+    eta: Option<[f64; 3]>,
+    k: Option<[f64; 3]>,
+    roughness: Option<ParamDef>,
+    metallic: Option<ParamDef>,
+    specular: Option<ParamDef>,
+    sheen: Option<ParamDef>,
+    clearcoat: Option<ParamDef>,
+    clearcoat_roughness: Option<ParamDef>,
+    transmission: Option<ParamDef>,
+}
+
+// Material parameter given as a number, a color or the name of a texture. Only principled
+// materials accept textures for anything but their albedo.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum ParamDef {
+    Number(f64),
+    Color([f64; 3]),
+    Texture(String),
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum ObjectKind {
+    Sphere,
+    Quad,
+    Triangle,
+    Disk,
+    Annulus,
+    Box,
+    Mesh,
+    Volume,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectDef {
+    #[serde(rename = "type")]
+    kind: ObjectKind,
+    // Optional for meshes, where it only applies to faces without a material of their own
+    material: Option<Spanned<String>>,
+    center: Option<[f64; 3]>,
+    // Makes a sphere move linearly from center to center_end over the shutter interval
+    center_end: Option<[f64; 3]>,
+    radius: Option<f64>,
+    // Corner of a quad or triangle, with u and v its edges (or the radii of disks and annuli)
+    origin: Option<[f64; 3]>,
+    u: Option<[f64; 3]>,
+    v: Option<[f64; 3]>,
+    // Hole size of an annulus relative to its outer radius
+    inner: Option<f64>,
+    // Opposite corners of a box
+    min: Option<[f64; 3]>,
+    max: Option<[f64; 3]>,
+    // Mesh or voxel grid file, relative to the scene file
+    file: Option<Spanned<String>>,
+    // Instance transform, applied as scale, then rotation around x, y and z in degrees, then
+    // translation
+    translate: Option<[f64; 3]>,
+    rotate: Option<[f64; 3]>,
+    scale: Option<ScaleDef>,
+    // Turns the object into the boundary of a uniform medium such as fog or smoke, using its
+    // material (usually isotropic) as the phase function. Multiplies the grid values of volumes.
+    density: Option<f64>,
+    // Volume emission, either a constant color or blackbody light from a temperature grid in
+    // kelvin with intensity the luminance of its hottest point
+    emit: Option<[f64; 3]>,
+    temperature: Option<Spanned<String>>,
+    intensity: Option<f64>,
+    // Poses to move between over the shutter interval, instead of a fixed transform
+    #[serde(default)]
+    keyframes: Vec<KeyframeDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDef {
+    // 0 when the shutter opens and 1 when it closes
+    time: f64,
+    translate: Option<[f64; 3]>,
+    rotate: Option<[f64; 3]>,
+    scale: Option<ScaleDef>,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(untagged)]
+enum ScaleDef {
+    Uniform(f64),
+    Axes([f64; 3]),
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+enum LightKind {
+    Point,
+    Spot,
+    Directional,
+}
+
+// Punctual light, with intensity in the units described on Light
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDef {
+    #[serde(rename = "type")]
+    kind: LightKind,
+    position: Option<[f64; 3]>,
+    // Where spot lights point and directional light travels
+    direction: Option<[f64; 3]>,
+    color: Option<[f64; 3]>,
+    intensity: Option<f64>,
+    // Spot light cone in degrees from its direction, fading out from inner to outer
+    inner_angle: Option<f64>,
+    outer_angle: Option<f64>,
+}
+
+// Loads a TOML scene file, or a glTF file by its .gltf or .glb extension
+pub fn load(path: &Path) -> Result<Scene, SceneError> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    if matches!(extension.as_str(), "gltf" | "glb") {
+        return gltf::load_gltf(path);
+    }
+
+    let source = fs::read_to_string(path).map_err(|err| SceneError {
+        path: Some(path.to_path_buf()),
+        location: None,
+        message: err.to_string(),
+    })?;
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    parse(&source, base_dir).map_err(|err| SceneError { path: Some(path.to_path_buf()), ..err })
+}
+
+// Parses a scene from source, resolving relative file references against base_dir
+pub fn parse(source: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let def: SceneDef = toml::from_str(source).map_err(|err| SceneError {
+        path: None,
+        location: err.span().map(|span| location_of(source, span.start)),
+        message: err.message().to_string(),
+    })?;
+
+    let mut loader = Loader { source, base_dir, textures: HashMap::new(), meshes: HashMap::new() };
+
+    for (name, texture) in &def.textures {
+        let texture = loader.texture(texture)?;
+        loader.textures.insert(name.as_str(), texture);
+    }
+
+    let mut materials = HashMap::new();
+    for (name, material) in &def.materials {
+        materials.insert(name.as_str(), loader.material(material)?);
+    }
+
+    let mut world = HittableList { objects: vec![] };
+    let mut lights = HittableList { objects: vec![] };
+    for object in &def.objects {
+        let material = match &object.get_ref().material {
+            Some(material) => {
+                let found = materials.get(material.get_ref().as_str()).ok_or_else(|| {
+                    loader.error(material.span(), format!("unknown material `{}`", material.get_ref()))
+                })?;
+                Some(Arc::clone(found))
+            }
+            None => None,
+        };
+
+        let emissive = object.get_ref().material.as_ref()
+            .and_then(|material| def.materials.get(material.get_ref()))
+            .is_some_and(|material| is_emissive(material.get_ref()));
+
+        if emissive && is_sampleable(object.get_ref()) {
+            if let Some(material) = &material {
+                loader.build_object(&mut lights, object, Arc::clone(material))?;
+            }
+        }
+
+        loader.add_object(&mut world, object, material)?;
+    }
+
+    let mut camera = match &def.camera {
+        Some(camera) => loader.camera(camera)?,
+        None => Camera::new(),
+    };
+    if let Some(background) = &def.background {
+        camera.background = loader.background(background)?;
+    }
+
+    let punctual_lights = def.lights.iter().map(|light| loader.light(light)).collect::<Result<_, _>>()?;
+
+    Ok(Scene {
+        world,
+        lights,
+        punctual_lights,
+        camera,
+    })
+}
+
+fn is_emissive(def: &MaterialDef) -> bool {
+    match def.kind {
+        MaterialKind::DiffuseLight => true,
+        MaterialKind::Principled => def.emit.is_some(),
+        _ => false,
+    }
+}
+
+// Only untransformed spheres and planar shapes can be sampled as lights so far
+fn is_sampleable(def: &ObjectDef) -> bool {
+    let shape = matches!(def.kind, ObjectKind::Sphere | ObjectKind::Quad | ObjectKind::Triangle | ObjectKind::Disk | ObjectKind::Annulus | ObjectKind::Box);
+    shape && def.density.is_none() && instance_transform(def).is_none() && def.keyframes.is_empty()
+}
+
+pub const BUILTIN_SCENES: &[&str] = &["spheres", "simple_light", "checkered_spheres", "perlin_spheres", "quads", "cornell_box", "cornell_smoke"];
+
+// Scenes constructed in code rather than loaded from a file
+pub fn builtin(name: &str) -> Option<Scene> {
+    match name {
+        "spheres" => Some(random_spheres()),
+        "simple_light" => Some(simple_light()),
+        "checkered_spheres" => Some(checkered_spheres()),
+        "perlin_spheres" => Some(perlin_spheres()),
+        "quads" => Some(quads()),
+        "cornell_box" => Some(cornell_box()),
+        "cornell_smoke" => Some(cornell_smoke()),
+        _ => None,
+    }
+}
+
+// Ground plane covered in small randomly placed spheres with three large ones in the middle
+fn random_spheres() -> Scene {
+    let mut world = HittableList { objects: vec![] };
+
+    let material_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+
+    world.add(Sphere::new(Vec3::new(0., -1000., 0.), 1000., material_ground));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat: f64 = random();
+            let center = Vec3::new(a as f64 + 0.9 * random::<f64>(), 0.2, b as f64 + 0.9 * random::<f64>());
+
+            if (center - Vec3::new(4., 0.2, 0.)).length() > 0.9 {
+                let sphere_material: Arc<dyn Material + Send>;
+
+                if choose_mat < 0.8 {
+                    let albedo = Color::random() * Color::random();
+                    sphere_material = Arc::new(Lambertian::new(albedo));
+                    let end_center = center + Vec3::new(0., gen_range(0.0..=0.5), 0.0);
+                    world.add(Sphere::new_moving(center, end_center, 0.2, sphere_material));
+                }
+                else if choose_mat < 0.95 {
+                    let albedo = Color::random_range(0.5, 1.0);
+                    let fuzz = gen_range(0.0..=0.5);
+                    sphere_material = Arc::new(Metal::new(albedo, fuzz));
+                    world.add(Sphere::new(center, 0.2, sphere_material));
+                }
+                else {
+                    sphere_material = Arc::new(Dialetric::new(1.5));
+                    world.add(Sphere::new(center, 0.2, sphere_material));
+                }
+            }
+        }
+    }
+
+    let glass = Arc::new(Dialetric::new(1.5));
+    let diffuse = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let metal = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+
+    world.add(Sphere::new(Vec3::new(0., 1., 0.), 1.0, glass));
+    world.add(Sphere::new(Vec3::new(-4., 1., 0.), 1.0, diffuse));
+    world.add(Sphere::new(Vec3::new(4., 1., 0.), 1.0, metal));
+
+    let lights = HittableList { objects: vec![] };
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.width = 400;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 20.0;
+    camera.lookfrom = Vec3::new(13., 2., 3.);
+    camera.lookat = Vec3::new(0., 0., 0.);
+    camera.vup = Vec3::new(0., 1., 0.);
+
+    camera.defocus_angle = 0.6;
+    camera.focus_distance = 10.0;
+
+    Scene { world, lights, punctual_lights: vec![], camera }
+}
+
+// Diffuse spheres lit only by a spherical area light against a black sky
+fn simple_light() -> Scene {
+    let mut world = HittableList { objects: vec![] };
+
+    let ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let diffuse = Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.2)));
+    let light = Arc::new(DiffuseLight::new(Color::new(4., 4., 4.)));
+
+    world.add(Sphere::new(Vec3::new(0., -1000., 0.), 1000., ground));
+    world.add(Sphere::new(Vec3::new(0., 2., 0.), 2., diffuse));
+    let lamp = Sphere::new(Vec3::new(0., 7., 0.), 2., light);
+    let mut lights = HittableList { objects: vec![] };
+    lights.add(lamp.clone());
+    world.add(lamp);
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.width = 400;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+    camera.background = Background::Solid(Color::ZERO);
+
+    camera.vfov = 20.0;
+    camera.lookfrom = Vec3::new(26., 3., 6.);
+    camera.lookat = Vec3::new(0., 2., 0.);
+    camera.vup = Vec3::new(0., 1., 0.);
+
+    camera.defocus_angle = 0.0;
+
+    Scene { world, lights, punctual_lights: vec![], camera }
+}
+
+// Two large spheres sharing one 3D checker texture
+fn checkered_spheres() -> Scene {
+    let mut world = HittableList { objects: vec![] };
+
+    let checker: Arc<dyn Texture> = Arc::new(CheckerTexture::from_colors(0.32, Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)));
+
+    world.add(Sphere::new(Vec3::new(0., -10., 0.), 10., Arc::new(Lambertian::from_texture(Arc::clone(&checker)))));
+    world.add(Sphere::new(Vec3::new(0., 10., 0.), 10., Arc::new(Lambertian::from_texture(checker))));
+
+    let lights = HittableList { objects: vec![] };
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.width = 400;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 20.0;
+    camera.lookfrom = Vec3::new(13., 2., 3.);
+    camera.lookat = Vec3::new(0., 0., 0.);
+    camera.vup = Vec3::new(0., 1., 0.);
+
+    camera.defocus_angle = 0.0;
+
+    Scene { world, lights, punctual_lights: vec![], camera }
+}
+
+// Marble ground with a wood sphere on top
+fn perlin_spheres() -> Scene {
+    let mut world = HittableList { objects: vec![] };
+
+    let marble = NoiseTexture::new(0, NoisePattern::Marble, 4.0);
+
+    let mut wood = NoiseTexture::new(1, NoisePattern::Wood, 3.0);
+    wood.low = Color::new(0.45, 0.25, 0.1);
+    wood.high = Color::new(0.8, 0.55, 0.3);
+
+    world.add(Sphere::new(Vec3::new(0., -1000., 0.), 1000., Arc::new(Lambertian::from_texture(Arc::new(marble)))));
+    world.add(Sphere::new(Vec3::new(0., 2., 0.), 2., Arc::new(Lambertian::from_texture(Arc::new(wood)))));
+
+    let lights = HittableList { objects: vec![] };
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 16.0 / 9.0;
+    camera.width = 400;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 20.0;
+    camera.lookfrom = Vec3::new(13., 2., 3.);
+    camera.lookat = Vec3::new(0., 0., 0.);
+    camera.vup = Vec3::new(0., 1., 0.);
+
+    camera.defocus_angle = 0.0;
+
+    Scene { world, lights, punctual_lights: vec![], camera }
+}
+
+// One of each planar shape facing the camera
+fn quads() -> Scene {
+    let mut world = HittableList { objects: vec![] };
+
+    let left_red = Arc::new(Lambertian::new(Color::new(1.0, 0.2, 0.2)));
+    let back_green = Arc::new(Lambertian::new(Color::new(0.2, 1.0, 0.2)));
+    let right_blue = Arc::new(Lambertian::new(Color::new(0.2, 0.2, 1.0)));
+    let upper_orange = Arc::new(Lambertian::new(Color::new(1.0, 0.5, 0.0)));
+    let lower_teal = Arc::new(Lambertian::new(Color::new(0.2, 0.8, 0.8)));
+
+    world.add(Quad::new(Vec3::new(-3., -2., 5.), Vec3::new(0., 0., -4.), Vec3::new(0., 4., 0.), left_red));
+    world.add(Quad::triangle(Vec3::new(-2., -2., 0.), Vec3::new(4., 0., 0.), Vec3::new(0., 4., 0.), back_green));
+    world.add(Quad::disk(Vec3::new(3., 0., 3.), Vec3::new(0., 0., 2.), Vec3::new(0., 2., 0.), right_blue));
+    world.add(Quad::annulus(Vec3::new(0., 3., 3.), Vec3::new(2., 0., 0.), Vec3::new(0., 0., 2.), 0.5, upper_orange));
+    world.add(Quad::new(Vec3::new(-2., -3., 5.), Vec3::new(4., 0., 0.), Vec3::new(0., 0., -4.), lower_teal));
+
+    let lights = HittableList { objects: vec![] };
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 1.0;
+    camera.width = 400;
+    camera.samples_per_pixel = 100;
+    camera.max_depth = 50;
+
+    camera.vfov = 80.0;
+    camera.lookfrom = Vec3::new(0., 0., 9.);
+    camera.lookat = Vec3::new(0., 0., 0.);
+    camera.vup = Vec3::new(0., 1., 0.);
+
+    camera.defocus_angle = 0.0;
+
+    Scene { world, lights, punctual_lights: vec![], camera }
+}
+
+fn cornell_box() -> Scene {
+    let mut world = HittableList { objects: vec![] };
+
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material + Send> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new(Color::new(15., 15., 15.)));
+
+    world.add(Quad::new(Vec3::new(555., 0., 0.), Vec3::new(0., 555., 0.), Vec3::new(0., 0., 555.), green));
+    world.add(Quad::new(Vec3::new(0., 0., 0.), Vec3::new(0., 555., 0.), Vec3::new(0., 0., 555.), red));
+    let lamp = Quad::new(Vec3::new(343., 554., 332.), Vec3::new(-130., 0., 0.), Vec3::new(0., 0., -105.), light);
+    let mut lights = HittableList { objects: vec![] };
+    lights.add(lamp.clone());
+    world.add(lamp);
+    world.add(Quad::new(Vec3::new(0., 0., 0.), Vec3::new(555., 0., 0.), Vec3::new(0., 0., 555.), Arc::clone(&white)));
+    world.add(Quad::new(Vec3::new(555., 555., 555.), Vec3::new(-555., 0., 0.), Vec3::new(0., 0., -555.), Arc::clone(&white)));
+    world.add(Quad::new(Vec3::new(0., 0., 555.), Vec3::new(555., 0., 0.), Vec3::new(0., 555., 0.), Arc::clone(&white)));
+
+    world.add(make_box(Vec3::new(130., 0., 65.), Vec3::new(295., 165., 230.), Arc::clone(&white)));
+    world.add(make_box(Vec3::new(265., 0., 295.), Vec3::new(430., 330., 460.), white));
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 1.0;
+    camera.width = 600;
+    camera.samples_per_pixel = 200;
+    camera.max_depth = 50;
+    camera.background = Background::Solid(Color::ZERO);
+
+    camera.vfov = 40.0;
+    camera.lookfrom = Vec3::new(278., 278., -800.);
+    camera.lookat = Vec3::new(278., 278., 0.);
+    camera.vup = Vec3::new(0., 1., 0.);
+
+    camera.defocus_angle = 0.0;
+
+    Scene { world, lights, punctual_lights: vec![], camera }
+}
+
+// Cornell box with rotated blocks of black and white smoke instead of solid boxes
+fn cornell_smoke() -> Scene {
+    let mut world = HittableList { objects: vec![] };
+
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white: Arc<dyn Material + Send> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new(Color::new(7., 7., 7.)));
+
+    world.add(Quad::new(Vec3::new(555., 0., 0.), Vec3::new(0., 555., 0.), Vec3::new(0., 0., 555.), green));
+    world.add(Quad::new(Vec3::new(0., 0., 0.), Vec3::new(0., 555., 0.), Vec3::new(0., 0., 555.), red));
+    let lamp = Quad::new(Vec3::new(113., 554., 127.), Vec3::new(330., 0., 0.), Vec3::new(0., 0., 305.), light);
+    let mut lights = HittableList { objects: vec![] };
+    lights.add(lamp.clone());
+    world.add(lamp);
+    world.add(Quad::new(Vec3::new(0., 555., 0.), Vec3::new(555., 0., 0.), Vec3::new(0., 0., 555.), Arc::clone(&white)));
+    world.add(Quad::new(Vec3::new(0., 0., 0.), Vec3::new(555., 0., 0.), Vec3::new(0., 0., 555.), Arc::clone(&white)));
+    world.add(Quad::new(Vec3::new(0., 0., 555.), Vec3::new(555., 0., 0.), Vec3::new(0., 555., 0.), Arc::clone(&white)));
+
+    let box1 = Transform::new(
+        Arc::new(make_box(Vec3::ZERO, Vec3::new(165., 330., 165.), Arc::clone(&white))),
+        Mat4::translation(Vec3::new(265., 0., 295.)) * Mat4::rotation(Vec3::new(0., 1., 0.), 15.),
+    ).unwrap();
+    let box2 = Transform::new(
+        Arc::new(make_box(Vec3::ZERO, Vec3::new(165., 165., 165.), white)),
+        Mat4::translation(Vec3::new(130., 0., 65.)) * Mat4::rotation(Vec3::new(0., 1., 0.), -18.),
+    ).unwrap();
+
+    world.add(ConstantMedium::new(Arc::new(box1), 0.01, Arc::new(Isotropic::new(Color::ZERO))));
+    world.add(ConstantMedium::new(Arc::new(box2), 0.01, Arc::new(Isotropic::new(Color::ONE))));
+
+    let mut camera = Camera::new();
+
+    camera.aspect_ratio = 1.0;
+    camera.width = 600;
+    camera.samples_per_pixel = 200;
+    camera.max_depth = 50;
+    camera.background = Background::Solid(Color::ZERO);
+
+    camera.vfov = 40.0;
+    camera.lookfrom = Vec3::new(278., 278., -800.);
+    camera.lookat = Vec3::new(278., 278., 0.);
+    camera.vup = Vec3::new(0., 1., 0.);
+
+    camera.defocus_angle = 0.0;
+
+    Scene { world, lights, punctual_lights: vec![], camera }
+}
+
+struct Loader<'a> {
+    source: &'a str,
+    base_dir: &'a Path,
+    textures: HashMap<&'a str, Arc<dyn Texture>>,
+    meshes: HashMap<(String, Option<String>), Arc<dyn Hittable + Send + Sync>>,
+}
+
+impl Loader<'_> {
+    fn error(&self, span: Range<usize>, message: String) -> SceneError {
+        SceneError {
+            path: None,
+            location: Some(location_of(self.source, span.start)),
+            message,
+        }
+    }
+
+    fn require<T: Clone>(&self, value: &Option<T>, span: Range<usize>, kind: &str, field: &str) -> Result<T, SceneError> {
+        value.clone().ok_or_else(|| self.error(span, format!("{kind} is missing required field `{field}`")))
+    }
+
+    fn image(&self, file: &Spanned<String>) -> Result<Arc<image::Image>, SceneError> {
+        let path = self.base_dir.join(file.get_ref());
+
+        image::load(&path)
+            .map(Arc::new)
+            .map_err(|err| self.error(file.span(), format!("cannot load image `{}`: {err}", path.display())))
+    }
+
+    fn texture(&self, def: &Spanned<TextureDef>) -> Result<Arc<dyn Texture>, SceneError> {
+        let span = def.span();
+        let def = def.get_ref();
+
+        Ok(match def.kind {
+            TextureKind::Solid => {
+                let color = self.require(&def.color, span, "solid texture", "color")?;
+                Arc::new(SolidColor::new(to_vec3(color)))
+            }
+            TextureKind::Checker => {
+                let scale = self.require(&def.scale, span.clone(), "checker texture", "scale")?;
+                let even = self.require(&def.even, span.clone(), "checker texture", "even")?;
+                let odd = self.require(&def.odd, span, "checker texture", "odd")?;
+                Arc::new(CheckerTexture::from_colors(scale, to_vec3(even), to_vec3(odd)))
+            }
+            TextureKind::Image => {
+                let file = self.require(&def.file, span, "image texture", "file")?;
+                Arc::new(ImageTexture::new(self.image(&file)?))
+            }
+            TextureKind::Noise => {
+                let pattern = match self.require(&def.pattern, span, "noise texture", "pattern")? {
+                    NoisePatternDef::Noise => NoisePattern::Noise,
+                    NoisePatternDef::Fbm => NoisePattern::Fbm,
+                    NoisePatternDef::Turbulence => NoisePattern::Turbulence,
+                    NoisePatternDef::Marble => NoisePattern::Marble,
+                    NoisePatternDef::Wood => NoisePattern::Wood,
+                };
+
+                let mut texture = NoiseTexture::new(def.seed.unwrap_or(0), pattern, def.scale.unwrap_or(1.0));
+                if let Some(octaves) = def.octaves { texture.octaves = octaves; }
+                if let Some(low) = def.low { texture.low = to_vec3(low); }
+                if let Some(high) = def.high { texture.high = to_vec3(high); }
+
+                Arc::new(texture)
+            }
+        })
+    }
+
+    // Materials take either a named texture or a constant albedo color
+    fn albedo(&self, def: &MaterialDef, span: Range<usize>, kind: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        if let Some(name) = &def.texture {
+            return self.textures.get(name.get_ref().as_str()).cloned().ok_or_else(|| {
+                self.error(name.span(), format!("unknown texture `{}`", name.get_ref()))
+            });
+        }
+
+        let albedo = self.require(&def.albedo, span, kind, "albedo")?;
+        Ok(Arc::new(SolidColor::new(to_vec3(albedo))))
+    }
+
+    fn parameter(&self, def: &ParamDef, span: Range<usize>) -> Result<Arc<dyn Texture>, SceneError> {
+        Ok(match def {
+            ParamDef::Number(value) => Arc::new(SolidColor::new(Color::splat(*value))),
+            ParamDef::Color(color) => Arc::new(SolidColor::new(to_vec3(*color))),
+            ParamDef::Texture(name) => self.textures.get(name.as_str()).cloned()
+                .ok_or_else(|| self.error(span, format!("unknown texture `{name}`")))?,
+        })
+    }
+
+    // Parameters that can't vary over the surface, with numbers standing for gray
+    fn constant(&self, def: &ParamDef, span: Range<usize>, kind: &str, field: &str) -> Result<Color, SceneError> {
+        match def {
+            ParamDef::Number(value) => Ok(Color::splat(*value)),
+            ParamDef::Color(color) => Ok(to_vec3(*color)),
+            ParamDef::Texture(_) => Err(self.error(span, format!("`{field}` of {kind} can't be a texture"))),
+        }
+    }
+
+    fn roughness(&self, def: &MaterialDef, span: Range<usize>, kind: &str) -> Result<f64, SceneError> {
+        match &def.roughness {
+            None => Ok(0.0),
+            Some(ParamDef::Number(roughness)) => Ok(*roughness),
+            Some(_) => Err(self.error(span, format!("`roughness` of {kind} must be a number"))),
+        }
+    }
+
+    fn material(&self, def: &Spanned<MaterialDef>) -> Result<Arc<dyn Material + Send>, SceneError> {
+        let span = def.span();
+        let def = def.get_ref();
+
+        Ok(match def.kind {
+            MaterialKind::Lambertian => {
+                let texture = self.albedo(def, span, "lambertian material")?;
+                Arc::new(Lambertian::from_texture(texture))
+            }
+            MaterialKind::Metal => {
+                let texture = self.albedo(def, span, "metal material")?;
+                Arc::new(Metal::from_texture(texture, def.fuzz.unwrap_or(0.0)))
+            }
+            MaterialKind::Dielectric => {
+                let refraction_index = self.require(&def.refraction_index, span, "dielectric material", "refraction_index")?;
+                Arc::new(Dialetric::new(refraction_index))
+            }
+            MaterialKind::DiffuseLight => {
+                let emit = self.require(&def.emit, span.clone(), "diffuse_light material", "emit")?;
+                Arc::new(DiffuseLight::new(self.constant(&emit, span, "diffuse_light material", "emit")?))
+            }
+            MaterialKind::Isotropic => {
+                let texture = self.albedo(def, span, "isotropic material")?;
+                Arc::new(Isotropic::from_texture(texture))
+            }
+            MaterialKind::Conductor => {
+                let roughness = self.roughness(def, span.clone(), "conductor material")?;
+
+                match def.metal {
+                    Some(metal) => {
+                        let metal = match metal {
+                            MetalDef::Gold => MetalKind::Gold,
+                            MetalDef::Copper => MetalKind::Copper,
+                            MetalDef::Aluminium => MetalKind::Aluminium,
+                            MetalDef::Silver => MetalKind::Silver,
+                        };
+                        Arc::new(Conductor::from_metal(metal, roughness))
+                    }
+                    None => {
+                        let eta = self.require(&def.eta, span.clone(), "conductor material", "eta")?;
+                        let k = self.require(&def.k, span, "conductor material", "k")?;
+                        Arc::new(Conductor::new(to_vec3(eta), to_vec3(k), roughness))
+                    }
+                }
+            }
+            MaterialKind::RoughDielectric => {
+                let refraction_index = self.require(&def.refraction_index, span.clone(), "rough_dielectric material", "refraction_index")?;
+                let roughness = self.roughness(def, span, "rough_dielectric material")?;
+                Arc::new(RoughDielectric::new(refraction_index, roughness))
+            }
+            MaterialKind::Principled => {
+                let mut material = Principled::from_texture(self.albedo(def, span.clone(), "principled material")?);
+
+                let parameters = [
+                    (&mut material.metallic, &def.metallic),
+                    (&mut material.roughness, &def.roughness),
+                    (&mut material.specular, &def.specular),
+                    (&mut material.sheen, &def.sheen),
+                    (&mut material.clearcoat, &def.clearcoat),
+                    (&mut material.clearcoat_roughness, &def.clearcoat_roughness),
+                    (&mut material.transmission, &def.transmission),
+                    (&mut material.emission, &def.emit),
+                ];
+                for (parameter, def) in parameters {
+                    if let Some(def) = def {
+                        *parameter = self.parameter(def, span.clone())?;
+                    }
+                }
+                if let Some(refraction_index) = def.refraction_index {
+                    material.refraction_index = refraction_index;
+                }
+
+                Arc::new(material)
+            }
+        })
+    }
+
+    fn camera(&self, def: &Spanned<CameraDef>) -> Result<Camera, SceneError> {
+        let span = def.span();
+        let def = def.get_ref();
+
+        if let Some(width) = def.width.filter(|&width| width < 1) {
+            return Err(self.error(span, format!("camera width must be at least 1, got {width}")));
+        }
+        if let Some(samples) = def.samples_per_pixel.filter(|&samples| samples < 1) {
+            return Err(self.error(span, format!("camera samples_per_pixel must be at least 1, got {samples}")));
+        }
+        if let Some(max_depth) = def.max_depth.filter(|&depth| depth < 0) {
+            return Err(self.error(span, format!("camera max_depth must not be negative, got {max_depth}")));
+        }
+        if let Some(aspect_ratio) = def.aspect_ratio.filter(|&ratio| ratio <= 0.0 || ratio.is_nan()) {
+            return Err(self.error(span, format!("camera aspect_ratio must be positive, got {aspect_ratio}")));
+        }
+
+        let mut camera = Camera::new();
+        if let Some(aspect_ratio) = def.aspect_ratio { camera.aspect_ratio = aspect_ratio; }
+        if let Some(width) = def.width { camera.width = width; }
+        if let Some(samples_per_pixel) = def.samples_per_pixel { camera.samples_per_pixel = samples_per_pixel; }
+        if let Some(max_depth) = def.max_depth { camera.max_depth = max_depth; }
+        if let Some(vfov) = def.vfov { camera.vfov = vfov; }
+        if let Some(lookfrom) = def.lookfrom { camera.lookfrom = to_vec3(lookfrom); }
+        if let Some(lookat) = def.lookat { camera.lookat = to_vec3(lookat); }
+        if let Some(vup) = def.vup { camera.vup = to_vec3(vup); }
+        if let Some(defocus_angle) = def.defocus_angle { camera.defocus_angle = defocus_angle; }
+        if let Some(focus_distance) = def.focus_distance { camera.focus_distance = focus_distance; }
+
+        for key in &def.keyframes {
+            let lookfrom = key.lookfrom.map_or(camera.lookfrom, to_vec3);
+            let lookat = key.lookat.map_or(camera.lookat, to_vec3);
+            if (lookfrom - lookat).length_squared() == 0.0 {
+                return Err(self.error(span, format!("camera keyframe at time {} has lookfrom and lookat in the same place", key.time)));
+            }
+
+            camera.keyframes.push(CameraKeyframe { time: key.time, lookfrom, lookat });
+        }
+
+        Ok(camera)
+    }
+
+    fn background(&self, def: &Spanned<BackgroundDef>) -> Result<Background, SceneError> {
+        let span = def.span();
+        let def = def.get_ref();
+
+        Ok(match def.kind {
+            BackgroundKind::Solid => {
+                let color = self.require(&def.color, span, "solid background", "color")?;
+                Background::Solid(to_vec3(color))
+            }
+            BackgroundKind::Gradient => Background::Gradient {
+                horizon: def.horizon.map_or(SKY_HORIZON, to_vec3),
+                zenith: def.zenith.map_or(SKY_ZENITH, to_vec3),
+            },
+            BackgroundKind::Environment => {
+                let file = self.require(&def.image, span, "environment background", "image")?;
+
+                let mut map = EnvironmentMap::new(self.image(&file)?);
+                map.rotation = def.rotation.unwrap_or(0.0);
+                map.intensity = def.intensity.unwrap_or(1.0);
+
+                Background::Environment(map)
+            }
+            BackgroundKind::Sky => {
+                let elevation = def.elevation.unwrap_or(45.0);
+                if !(0.0..=90.0).contains(&elevation) {
+                    return Err(self.error(span, format!("sun elevation must be between 0 and 90 degrees, got {elevation}")));
+                }
+
+                // Range the sky model gives sensible colors for
+                let turbidity = def.turbidity.unwrap_or(3.0);
+                if !(2.0..=10.0).contains(&turbidity) {
+                    return Err(self.error(span, format!("turbidity must be between 2 and 10, got {turbidity}")));
+                }
+
+                let mut sky = Sky::new(elevation, def.azimuth.unwrap_or(0.0), turbidity);
+                sky.intensity = def.intensity.unwrap_or(1.0);
+
+                Background::Sky(sky)
+            }
+        })
+    }
+
+    fn light(&self, def: &Spanned<LightDef>) -> Result<Light, SceneError> {
+        let span = def.span();
+        let def = def.get_ref();
+
+        let intensity = def.intensity.unwrap_or(1.0);
+        if intensity < 0.0 {
+            return Err(self.error(span, format!("light intensity must not be negative, got {intensity}")));
+        }
+        let intensity = intensity * def.color.map_or(Color::ONE, to_vec3);
+
+        Ok(match def.kind {
+            LightKind::Point => {
+                let position = self.require(&def.position, span, "point light", "position")?;
+                Light::point(to_vec3(position), intensity)
+            }
+            LightKind::Spot => {
+                let position = self.require(&def.position, span.clone(), "spot light", "position")?;
+                let direction = self.light_direction(&def.direction, span.clone(), "spot light")?;
+
+                let outer_angle = def.outer_angle.unwrap_or(45.0);
+                if !(0.0..=90.0).contains(&outer_angle) {
+                    return Err(self.error(span, format!("spot light outer_angle must be between 0 and 90 degrees, got {outer_angle}")));
+                }
+                let inner_angle = def.inner_angle.unwrap_or(0.0);
+                if !(0.0..=outer_angle).contains(&inner_angle) {
+                    return Err(self.error(span, format!("spot light inner_angle must be between 0 and outer_angle ({outer_angle}), got {inner_angle}")));
+                }
+
+                Light::spot(to_vec3(position), direction, intensity, inner_angle, outer_angle)
+            }
+            LightKind::Directional => {
+                let direction = self.light_direction(&def.direction, span, "directional light")?;
+                Light::directional(direction, intensity)
+            }
+        })
+    }
+
+    fn light_direction(&self, direction: &Option<[f64; 3]>, span: Range<usize>, kind: &str) -> Result<Vec3, SceneError> {
+        let direction = to_vec3(self.require(direction, span.clone(), kind, "direction")?);
+        if direction.length_squared() == 0.0 {
+            return Err(self.error(span, format!("{kind} direction must not be zero")));
+        }
+
+        Ok(direction)
+    }
+
+    fn add_object(&mut self, world: &mut HittableList, def: &Spanned<ObjectDef>, material: Option<Arc<dyn Material + Send>>) -> Result<(), SceneError> {
+        let span = def.span();
+        let object = def.get_ref();
+
+        let material: Arc<dyn Material + Send> = match (material, object.kind) {
+            (Some(material), _) => material,
+            (None, ObjectKind::Mesh) => Arc::new(Lambertian::new(Color::splat(0.8))),
+            (None, _) => return Err(self.error(span, "object is missing required field `material`".to_string())),
+        };
+
+        // Volumes use the density as a scale for their grid
+        let Some(density) = object.density.filter(|_| !matches!(object.kind, ObjectKind::Volume)) else {
+            return self.place_object(world, def, material);
+        };
+
+        if density <= 0.0 {
+            return Err(self.error(span, format!("density must be positive, got {density}")));
+        }
+
+        // The object only bounds the medium, its material becomes the phase function
+        let mut boundary = HittableList { objects: vec![] };
+        self.place_object(&mut boundary, def, Arc::clone(&material))?;
+        world.add(ConstantMedium::new(Arc::new(boundary), density, material));
+
+        Ok(())
+    }
+
+    // Adds the object to world, wrapped in its transform if it has one
+    fn place_object(&mut self, world: &mut HittableList, def: &Spanned<ObjectDef>, material: Arc<dyn Material + Send>) -> Result<(), SceneError> {
+        let span = def.span();
+        let object = def.get_ref();
+
+        let matrix = instance_transform(object);
+        if matrix.is_none() && object.keyframes.is_empty() {
+            return self.build_object(world, def, material);
+        }
+        if matrix.is_some() && !object.keyframes.is_empty() {
+            return Err(self.error(span, "object can't have both keyframes and translate, rotate or scale".to_string()));
+        }
+
+        let shared: Arc<dyn Hittable + Send + Sync> = match object.kind {
+            ObjectKind::Mesh => self.shared_mesh(object, span.clone(), material)?,
+            _ => {
+                let mut local = HittableList { objects: vec![] };
+                self.build_object(&mut local, def, material)?;
+                Arc::new(local)
+            }
+        };
+
+        match matrix {
+            Some(matrix) => {
+                let transform = Transform::new(shared, matrix)
+                    .ok_or_else(|| self.error(span, "object transform can't be inverted".to_string()))?;
+                world.add(transform);
+            }
+            None => {
+                let keyframes = object.keyframes.iter().map(|key| pose(key.time, key.translate, key.rotate, key.scale)).collect();
+                let animated = AnimatedTransform::new(shared, keyframes)
+                    .ok_or_else(|| self.error(span, "keyframe scales must be non-zero and keep their sign".to_string()))?;
+                world.add(animated);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Meshes are loaded once per file and material, and shared by all instances
+    fn shared_mesh(&mut self, def: &ObjectDef, span: Range<usize>, material: Arc<dyn Material + Send>) -> Result<Arc<dyn Hittable + Send + Sync>, SceneError> {
+        let file = self.require(&def.file, span, "mesh", "file")?;
+        let key = (file.get_ref().clone(), def.material.as_ref().map(|material| material.get_ref().clone()));
+
+        if let Some(mesh) = self.meshes.get(&key) {
+            return Ok(Arc::clone(mesh));
+        }
+
+        let mesh: Arc<dyn Hittable + Send + Sync> = Arc::new(FlatBvh::new(self.mesh(&file, material)?));
+        self.meshes.insert(key, Arc::clone(&mesh));
+
+        Ok(mesh)
+    }
+
+    fn mesh(&self, file: &Spanned<String>, material: Arc<dyn Material + Send>) -> Result<HittableList, SceneError> {
+        let path = self.base_dir.join(file.get_ref());
+
+        mesh::load(&path, material)
+            .map_err(|err| self.error(file.span(), format!("cannot load mesh `{}`: {err}", path.display())))
+    }
+
+    fn grid(&self, file: &Spanned<String>) -> Result<VoxelGrid, SceneError> {
+        let path = self.base_dir.join(file.get_ref());
+
+        voxel::load_vol(&path)
+            .map_err(|err| self.error(file.span(), format!("cannot load voxel grid `{}`: {err}", path.display())))
+    }
+
+    fn build_object(&self, world: &mut HittableList, def: &Spanned<ObjectDef>, material: Arc<dyn Material + Send>) -> Result<(), SceneError> {
+        let span = def.span();
+        let def = def.get_ref();
+
+        match def.kind {
+            ObjectKind::Sphere => {
+                let center = to_vec3(self.require(&def.center, span.clone(), "sphere", "center")?);
+                let radius = self.require(&def.radius, span, "sphere", "radius")?;
+
+                match def.center_end {
+                    Some(center_end) => world.add(Sphere::new_moving(center, to_vec3(center_end), radius, material)),
+                    None => world.add(Sphere::new(center, radius, material)),
+                }
+            }
+            ObjectKind::Quad | ObjectKind::Triangle => {
+                let kind = if matches!(def.kind, ObjectKind::Quad) { "quad" } else { "triangle" };
+                let origin = to_vec3(self.require(&def.origin, span.clone(), kind, "origin")?);
+                let u = to_vec3(self.require(&def.u, span.clone(), kind, "u")?);
+                let v = to_vec3(self.require(&def.v, span, kind, "v")?);
+
+                match def.kind {
+                    ObjectKind::Quad => world.add(Quad::new(origin, u, v, material)),
+                    _ => world.add(Quad::triangle(origin, u, v, material)),
+                }
+            }
+            ObjectKind::Disk | ObjectKind::Annulus => {
+                let kind = if matches!(def.kind, ObjectKind::Disk) { "disk" } else { "annulus" };
+                let center = to_vec3(self.require(&def.center, span.clone(), kind, "center")?);
+                let u = to_vec3(self.require(&def.u, span.clone(), kind, "u")?);
+                let v = to_vec3(self.require(&def.v, span.clone(), kind, "v")?);
+
+                match def.kind {
+                    ObjectKind::Disk => world.add(Quad::disk(center, u, v, material)),
+                    _ => {
+                        let inner = self.require(&def.inner, span, kind, "inner")?;
+                        world.add(Quad::annulus(center, u, v, inner, material));
+                    }
+                }
+            }
+            ObjectKind::Box => {
+                let min = to_vec3(self.require(&def.min, span.clone(), "box", "min")?);
+                let max = to_vec3(self.require(&def.max, span, "box", "max")?);
+
+                world.add(make_box(min, max, material));
+            }
+            ObjectKind::Mesh => {
+                let file = self.require(&def.file, span, "mesh", "file")?;
+                world.objects.extend(self.mesh(&file, material)?.objects);
+            }
+            ObjectKind::Volume => {
+                let file = self.require(&def.file, span.clone(), "volume", "file")?;
+                let density = self.grid(&file)?;
+                let scale = def.density.unwrap_or(1.0);
+                if scale < 0.0 {
+                    return Err(self.error(span, format!("density must not be negative, got {scale}")));
+                }
+
+                let emission = match (def.emit, &def.temperature) {
+                    (Some(_), Some(_)) => return Err(self.error(span, "volume can't have both emit and temperature".to_string())),
+                    (Some(emit), None) => Some(Emission::Constant(to_vec3(emit))),
+                    (None, Some(temperature)) => Some(Emission::Blackbody {
+                        temperature: self.grid(temperature)?,
+                        intensity: def.intensity.unwrap_or(1.0),
+                    }),
+                    (None, None) => None,
+                };
+
+                match emission {
+                    Some(emission) => world.add(GridMedium::with_emission(density, scale, material, emission)),
+                    None => world.add(GridMedium::new(density, scale, material)),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Matrix for the object's scale, rotate and translate fields, None if it has none of them
+fn instance_transform(def: &ObjectDef) -> Option<Mat4> {
+    if def.translate.is_none() && def.rotate.is_none() && def.scale.is_none() {
+        return None;
+    }
+
+    Some(pose(0.0, def.translate, def.rotate, def.scale).matrix())
+}
+
+fn pose(time: f64, translate: Option<[f64; 3]>, rotate: Option<[f64; 3]>, scale: Option<ScaleDef>) -> Keyframe {
+    let scale = match scale {
+        None => Vec3::ONE,
+        Some(ScaleDef::Uniform(factor)) => Vec3::splat(factor),
+        Some(ScaleDef::Axes(factors)) => to_vec3(factors),
+    };
+
+    Keyframe::new(time, translate.map_or(Vec3::ZERO, to_vec3), Quat::from_euler(rotate.unwrap_or_default()), scale)
+}
+
+fn to_vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+// Converts a byte offset into a 1 based line and column
+fn location_of(source: &str, offset: usize) -> Location {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let column = before[line_start..].chars().count() + 1;
+
+    Location { line, column }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> SceneError {
+        match parse(source, Path::new(".")) {
+            Ok(_) => panic!("expected the scene to be rejected"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn parses_bundled_scenes() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for name in ["three_spheres.toml", "cornell_box.toml"] {
+            let scene = load(&dir.join(name)).unwrap_or_else(|err| panic!("{err}"));
+            assert!(!scene.world.objects.is_empty());
+        }
+    }
+
+    #[test]
+    fn parses_camera_and_objects() {
+        let scene = parse(r#"
+            [camera]
+            width = 64
+            samples_per_pixel = 4
+            max_depth = 0
+
+            [materials.light]
+            type = "diffuse_light"
+            emit = [4.0, 4.0, 4.0]
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 1.0
+            material = "light"
+        "#, Path::new(".")).unwrap_or_else(|err| panic!("{err}"));
+
+        assert_eq!((scene.camera.width, scene.camera.samples_per_pixel, scene.camera.max_depth), (64, 4, 0));
+        assert_eq!(scene.world.objects.len(), 1);
+        assert_eq!(scene.lights.objects.len(), 1);
+    }
+
+    #[test]
+    fn empty_scene_uses_default_camera() {
+        let scene = parse("", Path::new(".")).unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(scene.camera.width, Camera::new().width);
+        assert!(scene.world.objects.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_camera() {
+        let err = parse_error("\n[camera]\nwidth = 0\n");
+        assert_eq!(err.location, Some(Location { line: 2, column: 1 }));
+        assert_eq!(err.message, "camera width must be at least 1, got 0");
+
+        assert_eq!(parse_error("[camera]\nsamples_per_pixel = 0").message, "camera samples_per_pixel must be at least 1, got 0");
+        assert_eq!(parse_error("[camera]\nmax_depth = -1").message, "camera max_depth must not be negative, got -1");
+        assert_eq!(parse_error("[camera]\naspect_ratio = 0.0").message, "camera aspect_ratio must be positive, got 0");
+    }
+
+    #[test]
+    fn parses_camera_keyframes() {
+        let camera = "[camera]\nlookfrom = [0.0, 0.0, 5.0]\nlookat = [0.0, 0.0, 0.0]\n";
+        let scene = parse(&format!("{camera}[[camera.keyframes]]\ntime = 1.0\nlookfrom = [1.0, 0.0, 5.0]\n"), Path::new("."))
+            .unwrap_or_else(|err| panic!("{err}"));
+
+        let key = scene.camera.keyframes[0];
+        assert_eq!((key.time, key.lookfrom.x, key.lookat.z), (1.0, 1.0, 0.0));
+
+        let err = parse_error(&format!("{camera}[[camera.keyframes]]\ntime = 0.5\nlookfrom = [0.0, 0.0, 0.0]\n"));
+        assert_eq!(err.message, "camera keyframe at time 0.5 has lookfrom and lookat in the same place");
+    }
+
+    #[test]
+    fn rejects_invalid_lights() {
+        let err = parse_error("[[lights]]\ntype = \"directional\"\ndirection = [0.0, 0.0, 0.0]\n");
+        assert_eq!(err.location, Some(Location { line: 1, column: 1 }));
+        assert_eq!(err.message, "directional light direction must not be zero");
+
+        let spot = "[[lights]]\ntype = \"spot\"\nposition = [0.0, 1.0, 0.0]\ndirection = [0.0, -1.0, 0.0]\n";
+        assert_eq!(parse_error(&format!("{spot}outer_angle = 120.0")).message, "spot light outer_angle must be between 0 and 90 degrees, got 120");
+        assert_eq!(
+            parse_error(&format!("{spot}inner_angle = 30.0\nouter_angle = 20.0")).message,
+            "spot light inner_angle must be between 0 and outer_angle (20), got 30",
+        );
+
+        let scene = parse(&format!("{spot}inner_angle = 20.0\nouter_angle = 20.0"), Path::new(".")).unwrap_or_else(|err| panic!("{err}"));
+        assert_eq!(scene.punctual_lights.len(), 1);
+    }
+
+    #[test]
+    fn reports_location_of_toml_errors() {
+        let err = parse_error("[camera]\nwidth = \"wide\"\n");
+        assert_eq!(err.location, Some(Location { line: 2, column: 9 }));
+
+        let err = parse_error("[camera]\nzoom = 2.0\n");
+        assert!(err.message.contains("unknown field `zoom`"), "{err}");
+    }
+
+    #[test]
+    fn rejects_unknown_material() {
+        let err = parse_error("[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"gold\"\n");
+        assert_eq!(err.location, Some(Location { line: 5, column: 12 }));
+        assert_eq!(err.message, "unknown material `gold`");
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        let err = parse_error("[materials.matte]\ntype = \"lambertian\"\n");
+        assert_eq!(err.message, "lambertian material is missing required field `albedo`");
+    }
+
+    #[test]
+    fn display_includes_path_and_location() {
+        let err = SceneError {
+            path: Some(PathBuf::from("scene.toml")),
+            location: Some(Location { line: 3, column: 7 }),
+            message: "oops".to_string(),
+        };
+        assert_eq!(err.to_string(), "scene.toml:3:7: oops");
+    }
+}
@@ -0,0 +1,155 @@
+use crate::color::{blackbody, xyy_to_rgb};
+use crate::onb::Onb;
+use crate::pdf::Pdf;
+use crate::rng::random;
+use crate::vec3::{Color, Vec3};
+use std::f64::consts::PI;
+
+// Angular radius of the sun seen from the ground, in degrees
+const SUN_RADIUS: f64 = 0.2667;
+// Luminance of the sun above the atmosphere in kcd/m^2, the unit the sky model works in
+const SUN_LUMINANCE: f64 = 1.96e6;
+// Luminance in kcd/m^2 that becomes a radiance of 1. Puts a white surface in sunlight close to 1.
+const LUMINANCE_UNIT: f64 = 40.0;
+
+// Clear daylight sky from the analytic model of Preetham, Shirley and Smits (1999). The sun is a
+// small bright disk in it, which renders sample directly as it is far too small to find by chance.
+#[derive(Debug, Clone)]
+pub struct Sky {
+    sun: Sun,
+    sun_radiance: Color,
+    // Perez distribution coefficients A to E of the luminance Y and the chromaticities x and y
+    perez: [[f64; 5]; 3],
+    // Y, x and y towards the zenith, divided by the Perez distribution there
+    zenith: [f64; 3],
+    // Multiplier applied to the sky and the sun
+    pub intensity: f64,
+}
+
+impl Sky {
+    // Sun elevation above the horizon and azimuth in degrees, with azimuth 0 towards -z and 90
+    // towards +x. Turbidity is the haziness of the air, from 2 for a very clear sky to around 10.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let t = turbidity;
+
+        let direction = Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+        let theta_s = PI / 2.0 - elevation;
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        // Luminance in kcd/m^2 and chromaticity straight up
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let angles = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let row = |r: [f64; 4]| r.iter().zip(angles).map(|(a, b)| a * b).sum::<f64>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_cx = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_cy = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let zenith = [zenith_y, zenith_cx, zenith_cy];
+        let zenith = std::array::from_fn(|i| zenith[i] / perez_function(perez[i], 1.0, theta_s));
+
+        Self {
+            sun: Sun::new(direction, SUN_RADIUS),
+            sun_radiance: SUN_LUMINANCE / LUMINANCE_UNIT * blackbody(5778.0) * sun_transmittance(theta_s, t),
+            perez,
+            zenith,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn sun(&self) -> &Sun {
+        &self.sun
+    }
+
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let d = direction.unit_vector();
+
+        // The model only covers the sky, below the horizon it carries on from there
+        let cos_theta = d.y.max(1e-4);
+        let cos_gamma = d.dot(&self.sun.direction).clamp(-1.0, 1.0);
+
+        let [luminance, x, y] = std::array::from_fn(|i| self.zenith[i] * perez_function(self.perez[i], cos_theta, cos_gamma.acos()));
+
+        let mut radiance = xyy_to_rgb(x, y, luminance / LUMINANCE_UNIT);
+        if cos_gamma >= self.sun.cos_radius {
+            radiance += self.sun_radiance;
+        }
+
+        self.intensity * radiance
+    }
+}
+
+// Relative sky brightness at zenith angle theta (by its cosine) and angle gamma from the sun
+fn perez_function([a, b, c, d, e]: [f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+// Fraction of sunlight at the red, green and blue wavelengths that makes it through the air,
+// scattered away by air molecules and haze (from the appendix of Preetham et al.)
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Color {
+    // Relative amount of air the light passes through, 1 with the sun straight up
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    // Wavelengths in micrometres
+    let channel = |lambda: f64| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-(rayleigh + aerosol) * air_mass).exp()
+    };
+
+    Color::new(channel(0.68), channel(0.55), channel(0.44))
+}
+
+// Directions towards a disk of light infinitely far away, such as the sun
+#[derive(Debug, Copy, Clone)]
+pub struct Sun {
+    direction: Vec3,
+    cos_radius: f64,
+}
+
+impl Sun {
+    // Angular radius in degrees
+    pub fn new(direction: Vec3, radius: f64) -> Self {
+        Self {
+            direction: direction.unit_vector(),
+            cos_radius: radius.to_radians().cos(),
+        }
+    }
+}
+
+// Uniform over the solid angle the disk covers
+impl Pdf for Sun {
+    fn value(&self, direction: Vec3) -> f64 {
+        if direction.unit_vector().dot(&self.direction) < self.cos_radius {
+            return 0.0;
+        }
+
+        1.0 / (2.0 * PI * (1.0 - self.cos_radius))
+    }
+
+    fn generate(&self) -> Vec3 {
+        let z = 1.0 + random::<f64>() * (self.cos_radius - 1.0);
+        let phi = 2.0 * PI * random::<f64>();
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        Onb::new(self.direction).to_world(Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
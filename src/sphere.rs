@@ -1,34 +1,63 @@
+use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
-use crate::material::Material;
+use crate::material::{random_unit_vector, Material};
+use crate::onb::Onb;
 use crate::ray::Ray;
+use crate::rng::random;
 use crate::vec3::Vec3;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Sphere {
     center: Ray,
     radius: f64,
     material: Arc<dyn Material + Send>,
+    bbox: AABB,
 }
 
 impl Sphere {
     pub fn new(center: Vec3, radius: f64, material: Arc<dyn Material + Send>) -> Self {
+        let radius = f64::max(0.0, radius);
+        let rvec = Vec3::splat(radius);
+
         Self {
             center: Ray::new(center, Vec3::ZERO, 0.0),
-            radius: f64::max(0.0, radius),
+            radius,
             material: Arc::clone(&material),
+            bbox: AABB::from_points(center - rvec, center + rvec),
         }
     }
 
     pub fn new_moving(begin_loc: Vec3, end_loc: Vec3, radius: f64, material: Arc<dyn Material + Send>) -> Self {
+        let radius = f64::max(0.0, radius);
+        let rvec = Vec3::splat(radius);
+
+        // Box must enclose the sphere over the entire shutter interval
+        let box0 = AABB::from_points(begin_loc - rvec, begin_loc + rvec);
+        let box1 = AABB::from_points(end_loc - rvec, end_loc + rvec);
+
         Self {
             center: Ray::new(begin_loc, end_loc - begin_loc, 0.0),
-            radius: f64::max(0.0, radius),
+            radius,
             material: Arc::clone(&material),
+            bbox: AABB::from_boxes(&box0, &box1),
         }
     }
 }
 
+impl Sphere {
+    // For a point on the unit sphere returns (u, v) where u is the angle around the y axis from
+    // x = -1 and v the angle from y = -1 to y = +1, both normalized to [0, 1]
+    fn get_sphere_uv(point: &Vec3) -> (f64, f64) {
+        let theta = f64::acos(-point.y);
+        let phi = f64::atan2(-point.z, point.x) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+}
+
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
         let current_center = self.center.at(ray.time);
@@ -61,12 +90,55 @@ impl Hittable for Sphere {
             normal: Vec3::ZERO,
             material: Arc::clone(&self.material),
             t: root,
+            u: 0.0,
+            v: 0.0,
+            color: None,
             front_face: false,
         };
 
         let outward_normal = (hr.point - current_center) / self.radius;
         hr.set_face_normal(ray, outward_normal);
+        (hr.u, hr.v) = Self::get_sphere_uv(&outward_normal);
 
         Some(hr)
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+    // Directions are sampled uniformly within the cone the sphere subtends, as seen when the
+    // shutter opens
+    fn pdf_value(&self, origin: Vec3, direction: Vec3) -> f64 {
+        if self.hit(&Ray::new(origin, direction, 0.0), Interval::new(0.001, f64::INFINITY)).is_none() {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center.origin - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        // From inside the sphere covers every direction
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        let direction = self.center.origin - origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            return random_unit_vector();
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + random::<f64>() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random::<f64>();
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        Onb::new(direction.unit_vector()).to_world(Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
@@ -0,0 +1,253 @@
+use crate::material::Material;
+use crate::mesh::{MeshData, MeshError};
+use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+// Reads an STL file, ASCII or binary. Facets are stored separately in STL, so identical corners are
+// merged into shared vertices.
+pub fn load_stl(path: &Path, material: Arc<dyn Material + Send>) -> Result<MeshData, MeshError> {
+    read_stl(&mut BufReader::new(File::open(path)?), material)
+}
+
+pub fn read_stl(input: &mut dyn Read, material: Arc<dyn Material + Send>) -> Result<MeshData, MeshError> {
+    let mut data = vec![];
+    input.read_to_end(&mut data)?;
+
+    let mut builder = Builder::default();
+
+    // Binary files may also start with "solid", so their size decides, and truncated binary files
+    // are told apart by not being text
+    let binary_size = data.get(HEADER_SIZE..HEADER_SIZE + 4)
+        .map(|count| HEADER_SIZE + 4 + TRIANGLE_SIZE * u32::from_le_bytes(count.try_into().unwrap()) as usize);
+
+    match std::str::from_utf8(&data) {
+        Ok(source) if binary_size != Some(data.len()) && source.starts_with("solid") => read_ascii(source, &mut builder)?,
+        _ => read_binary(&data, &mut builder)?,
+    }
+
+    if builder.indices.is_empty() {
+        return Err(MeshError::parse(None, "file contains no facets"));
+    }
+
+    Ok(MeshData {
+        positions: builder.positions,
+        normals: vec![],
+        uvs: vec![],
+        colors: vec![],
+        indices: builder.indices,
+        material,
+    })
+}
+
+#[derive(Default)]
+struct Builder {
+    positions: Vec<Vec3>,
+    indices: Vec<[u32; 3]>,
+    // Exact bit patterns of each position, for merging
+    lookup: HashMap<[u64; 3], u32>,
+}
+
+impl Builder {
+    fn vertex(&mut self, p: Vec3) -> u32 {
+        // Adding zero folds -0.0 into 0.0
+        let key = [(p.x + 0.0).to_bits(), (p.y + 0.0).to_bits(), (p.z + 0.0).to_bits()];
+        *self.lookup.entry(key).or_insert_with(|| {
+            self.positions.push(p);
+            (self.positions.len() - 1) as u32
+        })
+    }
+
+    fn facet(&mut self, corners: [Vec3; 3]) {
+        let indices = corners.map(|corner| self.vertex(corner));
+
+        // Facets collapsed to a line or point can't be hit and only slow down the BVH
+        if indices[0] != indices[1] && indices[1] != indices[2] && indices[2] != indices[0] {
+            self.indices.push(indices);
+        }
+    }
+}
+
+fn read_binary(data: &[u8], builder: &mut Builder) -> Result<(), MeshError> {
+    if data.len() < HEADER_SIZE + 4 {
+        return Err(MeshError::parse(None, "file too short for a binary STL header"));
+    }
+
+    let count = u32::from_le_bytes(data[HEADER_SIZE..HEADER_SIZE + 4].try_into().unwrap()) as usize;
+    let facets = &data[HEADER_SIZE + 4..];
+
+    if facets.len() < count * TRIANGLE_SIZE {
+        return Err(MeshError::parse(None, format!(
+            "header declares {count} facets but the file only holds {}", facets.len() / TRIANGLE_SIZE,
+        )));
+    }
+
+    let read_vec3 = |bytes: &[u8]| {
+        let component = |i: usize| f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap()) as f64;
+        Vec3::new(component(0), component(1), component(2))
+    };
+
+    // Each facet is a normal, three corners and a two byte attribute; the normal is recomputed from
+    // the winding order instead
+    for facet in facets.chunks_exact(TRIANGLE_SIZE).take(count) {
+        builder.facet([read_vec3(&facet[12..24]), read_vec3(&facet[24..36]), read_vec3(&facet[36..48])]);
+    }
+
+    Ok(())
+}
+
+fn read_ascii(source: &str, builder: &mut Builder) -> Result<(), MeshError> {
+    let mut corners = vec![];
+    let mut in_loop = false;
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let error = |message: String| MeshError::parse(Some(line_number), message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words[..] {
+            [] | ["solid", ..] | ["endsolid", ..] | ["facet", "normal", ..] => {}
+            ["outer", "loop"] => {
+                if in_loop {
+                    return Err(error("nested `outer loop`".to_string()));
+                }
+                in_loop = true;
+                corners.clear();
+            }
+            ["vertex", x, y, z] => {
+                if !in_loop {
+                    return Err(error("vertex outside of a loop".to_string()));
+                }
+
+                let parse = |c: &str| c.parse::<f64>().map_err(|_| error(format!("invalid number `{c}`")));
+                corners.push(Vec3::new(parse(x)?, parse(y)?, parse(z)?));
+            }
+            ["endloop"] => {
+                if !in_loop {
+                    return Err(error("`endloop` without `outer loop`".to_string()));
+                }
+                in_loop = false;
+
+                // Facets should be triangles but some exporters write polygons, which are split
+                // into a fan
+                if corners.len() < 3 {
+                    return Err(error(format!("facet with {} vertices", corners.len())));
+                }
+                for i in 2..corners.len() {
+                    builder.facet([corners[0], corners[i - 1], corners[i]]);
+                }
+            }
+            ["endfacet"] => {}
+            _ => return Err(error(format!("unexpected line `{}`", line.trim()))),
+        }
+    }
+
+    if in_loop {
+        return Err(MeshError::parse(None, "file ends inside a facet"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn read(bytes: &[u8]) -> Result<MeshData, MeshError> {
+        read_stl(&mut &bytes[..], Arc::new(Lambertian::new(Color::splat(0.5))))
+    }
+
+    fn parse_error(bytes: &[u8]) -> (Option<usize>, String) {
+        match read(bytes) {
+            Err(MeshError::Parse { line, message }) => (line, message),
+            Err(err) => panic!("expected a parse error, got `{err}`"),
+            Ok(_) => panic!("expected the file to be rejected"),
+        }
+    }
+
+    fn coords(points: &[Vec3]) -> Vec<[f64; 3]> {
+        points.iter().map(|p| [p.x, p.y, p.z]).collect()
+    }
+
+    // Two facets of a unit square sharing an edge
+    const FACETS: [[[f32; 3]; 3]; 2] = [
+        [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]],
+        [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+    ];
+
+    fn ascii() -> String {
+        let mut source = "solid square\n".to_string();
+        for facet in FACETS {
+            source += "facet normal 0 0 1\nouter loop\n";
+            for [x, y, z] in facet {
+                source += &format!("vertex {x} {y} {z}\n");
+            }
+            source += "endloop\nendfacet\n";
+        }
+        source + "endsolid square\n"
+    }
+
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut bytes = header.to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend((FACETS.len() as u32).to_le_bytes());
+        for facet in FACETS {
+            bytes.extend([0.0f32, 0.0, 1.0].iter().flat_map(|c| c.to_le_bytes()));
+            bytes.extend(facet.iter().flatten().flat_map(|c| c.to_le_bytes()));
+            bytes.extend([0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_ascii_and_merges_vertices() {
+        let mesh = read(ascii().as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn binary_matches_ascii() {
+        let a = read(ascii().as_bytes()).unwrap();
+
+        // Binary files are allowed to start with "solid" too
+        for header in [&b"binary square"[..], b"solid square"] {
+            let b = read(&binary(header)).unwrap();
+            assert_eq!(coords(&a.positions), coords(&b.positions));
+            assert_eq!(a.indices, b.indices);
+        }
+    }
+
+    #[test]
+    fn skips_degenerate_facets() {
+        let source = "solid line\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 0 0\nendloop\nendfacet\nendsolid\n";
+        assert_eq!(parse_error(source.as_bytes()).1, "file contains no facets");
+    }
+
+    #[test]
+    fn rejects_malformed_ascii() {
+        let source = ascii();
+        assert_eq!(parse_error(source.replace("vertex 1 0 0", "vertex 1 zero 0").as_bytes()), (Some(5), "invalid number `zero`".to_string()));
+        assert_eq!(parse_error(source.replacen("outer loop", "outer loop\nouter loop", 1).as_bytes()), (Some(4), "nested `outer loop`".to_string()));
+        assert_eq!(parse_error(b"solid\nvertex 0 0 0\n"), (Some(2), "vertex outside of a loop".to_string()));
+        assert_eq!(parse_error(b"solid\nouter loop\nvertex 0 0 0\nendloop\n"), (Some(4), "facet with 1 vertices".to_string()));
+        assert_eq!(parse_error(b"solid\nouter loop\nvertex 0 0 0\n").1, "file ends inside a facet");
+        assert_eq!(parse_error(b"solid\nfacets\n"), (Some(2), "unexpected line `facets`".to_string()));
+    }
+
+    #[test]
+    fn rejects_truncated_binary() {
+        assert_eq!(parse_error(&[0; 20]).1, "file too short for a binary STL header");
+
+        let bytes = binary(b"square");
+        assert_eq!(parse_error(&bytes[..bytes.len() - 1]).1, "header declares 2 facets but the file only holds 1");
+    }
+}
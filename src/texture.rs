@@ -0,0 +1,154 @@
+use crate::image::Image;
+use crate::perlin::Perlin;
+use crate::vec3::{Color, Vec3};
+use std::sync::Arc;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self {
+            albedo,
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: &Vec3) -> Color {
+        self.albedo
+    }
+}
+
+// Alternates between two textures in a 3D grid of cubes with sides of length scale
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        Self::new(scale, Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, point: &Vec3) -> Color {
+        let x = (self.inv_scale * point.x).floor() as i64;
+        let y = (self.inv_scale * point.y).floor() as i64;
+        let z = (self.inv_scale * point.z).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+// Maps an image onto the surface by its (u, v) coordinates, with v = 0 at the bottom of the image
+pub struct ImageTexture {
+    image: Arc<Image>,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> Self {
+        Self {
+            image,
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: &Vec3) -> Color {
+        if self.image.width() == 0 || self.image.height() == 0 {
+            // Solid cyan makes missing texture data easy to spot
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+
+        let x = ((u * self.image.width() as f64) as usize).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as f64) as usize).min(self.image.height() - 1);
+
+        self.image.get(x, y)
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NoisePattern {
+    // Plain smooth noise
+    Noise,
+    // Fractal Brownian motion
+    Fbm,
+    // Sum of absolute octaves, cloudy with sharp creases
+    Turbulence,
+    // Bands along z distorted by turbulence
+    Marble,
+    // Concentric rings around the y axis distorted by turbulence
+    Wood,
+}
+
+// Procedural texture blending from low to high color by a noise driven pattern
+pub struct NoiseTexture {
+    noise: Perlin,
+    pattern: NoisePattern,
+    // Frequency of the pattern, larger values give finer detail
+    pub scale: f64,
+    pub octaves: usize,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, pattern: NoisePattern, scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            pattern,
+            scale,
+            octaves: 7,
+            low: Color::ZERO,
+            high: Color::ONE,
+        }
+    }
+
+    // Pattern intensity in [0, 1] at point
+    fn intensity(&self, point: &Vec3) -> f64 {
+        let p = self.scale * *point;
+
+        let t = match self.pattern {
+            NoisePattern::Noise => 0.5 * (1.0 + self.noise.noise(&p)),
+            NoisePattern::Fbm => 0.5 * (1.0 + self.noise.fbm(&p, self.octaves)),
+            NoisePattern::Turbulence => self.noise.turbulence(&p, self.octaves),
+            NoisePattern::Marble => 0.5 * (1.0 + f64::sin(p.z + 10.0 * self.noise.turbulence(&p, self.octaves))),
+            NoisePattern::Wood => {
+                let rings = p.x.hypot(p.z) + 0.5 * self.noise.turbulence(&p, self.octaves);
+                rings - rings.floor()
+            }
+        };
+
+        t.clamp(0.0, 1.0)
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Vec3) -> Color {
+        let t = self.intensity(point);
+        (1.0 - t) * self.low + t * self.high
+    }
+}
@@ -0,0 +1,260 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::matrix::Mat4;
+use crate::quaternion::Quat;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+// Places an instance of a shared object in the world with an affine transform. Rays are moved
+// into object space for the intersection and the hit is moved back, so one object can be drawn
+// many times with different transforms without copying it.
+pub struct Transform {
+    object: Arc<dyn Hittable + Send + Sync>,
+    // Object to world space
+    matrix: Mat4,
+    // World to object space
+    inverse: Mat4,
+    // Object to world space for normals
+    normal_matrix: Mat4,
+    bbox: AABB,
+}
+
+impl Transform {
+    // None if matrix can't be inverted, such as a scale by zero
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, matrix: Mat4) -> Option<Self> {
+        let inverse = matrix.inverse()?;
+        let bbox = transform_box(&object.bounding_box(), &matrix);
+
+        Some(Self {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            bbox,
+        })
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    pub fn object(&self) -> &Arc<dyn Hittable + Send + Sync> {
+        &self.object
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // The direction isn't normalized, so t is the same in both spaces
+        let object_ray = Ray::new(
+            self.inverse.transform_point(ray.origin),
+            self.inverse.transform_vector(ray.direction),
+            ray.time,
+        );
+
+        let mut hit = self.object.hit(&object_ray, ray_t)?;
+
+        // Normals keep which side of the surface the ray is on, so front_face carries over
+        hit.point = self.matrix.transform_point(hit.point);
+        hit.normal = self.normal_matrix.transform_vector(hit.normal).unit_vector();
+
+        Some(hit)
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        let object_ray = Ray::new(
+            self.inverse.transform_point(ray.origin),
+            self.inverse.transform_vector(ray.direction),
+            ray.time,
+        );
+
+        self.object.transmittance(&object_ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Pose of an animated object at a point in the shutter interval, applied as scale, then rotation,
+// then translation
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Self {
+            time,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translation(self.translation) * self.rotation.to_mat4() * Mat4::scaling(self.scale)
+    }
+
+    // Cheaper than inverting matrix() since every part inverts on its own
+    fn inverse_matrix(&self) -> Mat4 {
+        Mat4::scaling(Vec3::ONE / self.scale) * self.rotation.conjugate().to_mat4() * Mat4::translation(-self.translation)
+    }
+
+    fn lerp(&self, other: &Keyframe, t: f64) -> Self {
+        Self {
+            time: (1.0 - t) * self.time + t * other.time,
+            translation: (1.0 - t) * self.translation + t * other.translation,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: (1.0 - t) * self.scale + t * other.scale,
+        }
+    }
+}
+
+// Bounding boxes of moving objects are built from this many poses between each pair of keyframes
+const MOTION_STEPS: usize = 32;
+
+// Like Transform, but interpolating between keyframes by the time of each ray so the object moves
+// during the shutter interval. Poses before the first or after the last keyframe are held.
+pub struct AnimatedTransform {
+    object: Arc<dyn Hittable + Send + Sync>,
+    keyframes: Vec<Keyframe>,
+    bbox: AABB,
+}
+
+impl AnimatedTransform {
+    // None without keyframes or when a scale component is zero or changes sign, which would make
+    // some pose impossible to invert
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, mut keyframes: Vec<Keyframe>) -> Option<Self> {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let first = keyframes.first()?;
+        let sign = |v: Vec3| [v.x.signum(), v.y.signum(), v.z.signum()];
+        let invertible = keyframes.iter().all(|key| {
+            key.scale.x != 0.0 && key.scale.y != 0.0 && key.scale.z != 0.0 && sign(key.scale) == sign(first.scale)
+        });
+        if !invertible {
+            return None;
+        }
+
+        let bbox = motion_box(&object.bounding_box(), &keyframes);
+
+        Some(Self {
+            object,
+            keyframes,
+            bbox,
+        })
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    // Interpolated pose at time
+    pub fn at(&self, time: f64) -> Keyframe {
+        let next = self.keyframes.partition_point(|key| key.time <= time);
+
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        a.lerp(b, (time - a.time) / (b.time - a.time))
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let pose = self.at(ray.time);
+        let inverse = pose.inverse_matrix();
+
+        let object_ray = Ray::new(
+            inverse.transform_point(ray.origin),
+            inverse.transform_vector(ray.direction),
+            ray.time,
+        );
+
+        let mut hit = self.object.hit(&object_ray, ray_t)?;
+
+        hit.point = pose.matrix().transform_point(hit.point);
+        hit.normal = inverse.transpose().transform_vector(hit.normal).unit_vector();
+
+        Some(hit)
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        let inverse = self.at(ray.time).inverse_matrix();
+
+        let object_ray = Ray::new(
+            inverse.transform_point(ray.origin),
+            inverse.transform_vector(ray.direction),
+            ray.time,
+        );
+
+        self.object.transmittance(&object_ray, ray_t)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Box enclosing the object in every pose. Poses are sampled between keyframes, and the box grows by
+// how far a rotating corner can bulge out past the straight line between two samples.
+fn motion_box(bbox: &AABB, keyframes: &[Keyframe]) -> AABB {
+    let mut result = transform_box(bbox, &keyframes[0].matrix());
+
+    // Farthest any point of the object is from its origin, which rotations turn around
+    let mut radius: f64 = 0.0;
+    for x in [bbox.x.min, bbox.x.max] {
+        for y in [bbox.y.min, bbox.y.max] {
+            for z in [bbox.z.min, bbox.z.max] {
+                radius = radius.max(Vec3::new(x, y, z).length());
+            }
+        }
+    }
+
+    for pair in keyframes.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+
+        for step in 1..=MOTION_STEPS {
+            let pose = a.lerp(b, step as f64 / MOTION_STEPS as f64);
+            result = AABB::from_boxes(&result, &transform_box(bbox, &pose.matrix()));
+        }
+
+        let max_scale = [a.scale, b.scale].iter().map(|s| s.x.abs().max(s.y.abs()).max(s.z.abs())).fold(0.0, f64::max);
+        let step_angle = a.rotation.angle_to(&b.rotation) / MOTION_STEPS as f64;
+        let bulge = radius * max_scale * (1.0 - (0.5 * step_angle).cos());
+
+        if bulge > 0.0 {
+            result = AABB::new(result.x.expand(2.0 * bulge), result.y.expand(2.0 * bulge), result.z.expand(2.0 * bulge));
+        }
+    }
+
+    result
+}
+
+// Box around all eight transformed corners of bbox
+fn transform_box(bbox: &AABB, matrix: &Mat4) -> AABB {
+    let mut result = AABB::EMPTY;
+
+    for x in [bbox.x.min, bbox.x.max] {
+        for y in [bbox.y.min, bbox.y.max] {
+            for z in [bbox.z.min, bbox.z.max] {
+                let corner = matrix.transform_point(Vec3::new(x, y, z));
+                result = AABB::from_boxes(&result, &AABB::from_points(corner, corner));
+            }
+        }
+    }
+
+    result
+}
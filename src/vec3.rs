@@ -1,11 +1,11 @@
-use rand::Rng;
 use std::ops::{Add, Mul, Sub};
 use std::{fmt::Display, ops};
 use crate::aabb::Axis;
+use crate::rng::gen_range;
 
 pub type Color = Vec3;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -26,21 +26,19 @@ impl Vec3 {
 
     // Creates new Vec3 with each component being randomly in the range [0.0, 1.0]
     pub fn random() -> Self {
-        let mut rng = rand::thread_rng();
         Vec3::new(
-            rng.gen_range(0.0..=1.0),
-            rng.gen_range(0.0..=1.0),
-            rng.gen_range(0.0..=1.0),
+            gen_range(0.0..=1.0),
+            gen_range(0.0..=1.0),
+            gen_range(0.0..=1.0),
         )
     }
 
     // Creates new Vec3 with each component being randomly in the range [min, max]
     pub fn random_range(min: f64, max: f64) -> Self {
-        let mut rng = rand::thread_rng();
         Vec3::new(
-            rng.gen_range(min..=max),
-            rng.gen_range(min..=max),
-            rng.gen_range(min..=max),
+            gen_range(min..=max),
+            gen_range(min..=max),
+            gen_range(min..=max),
         )
     }
 
@@ -240,10 +238,3 @@ impl ops::MulAssign<f64> for Vec3 {
         self.z *= rhs;
     }
 }
-
-
-impl Default for Vec3 {
-    fn default() -> Self {
-        Self { x: Default::default(), y: Default::default(), z: Default::default() }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,316 @@
+use crate::aabb::AABB;
+use crate::color::blackbody;
+use crate::hittable::{HitRecord, Hittable};
+use crate::interval::Interval;
+use crate::material::{Material, ScatterRecord};
+use crate::ray::Ray;
+use crate::rng::random;
+use crate::vec3::{Color, Vec3};
+use crate::voxel::VoxelGrid;
+use std::sync::Arc;
+
+// Uniform fog or smoke filling a closed boundary shape. Rays scatter at an exponentially
+// distributed distance inside it, so the probability of passing through drops with the distance
+// traveled and the density. The phase function material decides where they go next, usually
+// Isotropic.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable + Send + Sync>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material + Send>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable + Send + Sync>, density: f64, phase_function: Arc<dyn Material + Send>) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        // Where the ray enters and leaves the boundary, even if it starts inside
+        let mut entry = self.boundary.hit(ray, Interval::UNIVERSE)?;
+        let exit = self.boundary.hit(ray, Interval::new(entry.t + 0.0001, f64::INFINITY))?;
+
+        entry.t = entry.t.max(ray_t.min);
+        let exit_t = exit.t.min(ray_t.max);
+
+        if entry.t >= exit_t {
+            return None;
+        }
+
+        entry.t = entry.t.max(0.0);
+
+        let ray_length = ray.direction.length();
+        let distance_inside_boundary = (exit_t - entry.t) * ray_length;
+        let hit_distance = self.neg_inv_density * random::<f64>().ln();
+
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = entry.t + hit_distance / ray_length;
+
+        // There is no surface, so normal and front face are arbitrary
+        Some(HitRecord {
+            point: ray.at(t),
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            material: Arc::clone(&self.phase_function),
+            t,
+            u: 0.0,
+            v: 0.0,
+            color: None,
+            front_face: true,
+        })
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.boundary.bounding_box()
+    }
+
+    // Known exactly for a uniform medium, so shadow rays needn't sample it
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        let Some(entry) = self.boundary.hit(ray, Interval::UNIVERSE) else {
+            return 1.0;
+        };
+        let Some(exit) = self.boundary.hit(ray, Interval::new(entry.t + 0.0001, f64::INFINITY)) else {
+            return 1.0;
+        };
+
+        let entry_t = entry.t.max(ray_t.min).max(0.0);
+        let exit_t = exit.t.min(ray_t.max);
+        if entry_t >= exit_t {
+            return 1.0;
+        }
+
+        ((exit_t - entry_t) * ray.direction.length() / self.neg_inv_density).exp()
+    }
+}
+
+// Light given off by a grid medium at every collision
+pub enum Emission {
+    // The same radiance everywhere, so the glow follows the density
+    Constant(Color),
+    // Blackbody radiance for a temperature grid in kelvin. Brightness grows with the fourth power of
+    // the temperature, scaled so the hottest point of the grid has luminance intensity.
+    Blackbody { temperature: VoxelGrid, intensity: f64 },
+}
+
+// Temperatures in the blackbody lookup table, spanning zero to the hottest point of the grid
+const BLACKBODY_STEPS: usize = 1024;
+
+// Medium whose density varies through space, read from a voxel grid and multiplied by
+// density_scale. Collisions are found with delta tracking: tentative collisions are placed as if
+// the whole grid had its highest density, and each is kept with probability of the actual density
+// over that highest one, so empty regions are passed through and the result stays unbiased.
+pub struct GridMedium {
+    density: VoxelGrid,
+    density_scale: f64,
+    // Highest density anywhere in the grid, bounding the density for delta tracking
+    majorant: f64,
+    phase_function: Arc<dyn Material + Send>,
+    emission: Option<Emission>,
+    // Blackbody radiance at evenly spaced temperatures, for Emission::Blackbody
+    blackbody: Vec<Color>,
+}
+
+impl GridMedium {
+    pub fn new(density: VoxelGrid, density_scale: f64, phase_function: Arc<dyn Material + Send>) -> Self {
+        Self {
+            majorant: density.max() * density_scale,
+            density,
+            density_scale,
+            phase_function,
+            emission: None,
+            blackbody: vec![],
+        }
+    }
+
+    pub fn with_emission(density: VoxelGrid, density_scale: f64, phase_function: Arc<dyn Material + Send>, emission: Emission) -> Self {
+        let blackbody = match &emission {
+            Emission::Constant(_) => vec![],
+            Emission::Blackbody { temperature, intensity } => {
+                let max = temperature.max();
+                (0..=BLACKBODY_STEPS)
+                    .map(|i| {
+                        let kelvin = max * i as f64 / BLACKBODY_STEPS as f64;
+                        *intensity * (kelvin / max).powi(4) * blackbody(kelvin)
+                    })
+                    .collect()
+            }
+        };
+
+        Self {
+            emission: Some(emission),
+            blackbody,
+            ..Self::new(density, density_scale, phase_function)
+        }
+    }
+
+    fn emitted(&self, point: Vec3) -> Color {
+        match &self.emission {
+            None => Color::ZERO,
+            Some(Emission::Constant(radiance)) => *radiance,
+            Some(Emission::Blackbody { temperature, .. }) => {
+                let max = temperature.max();
+                if max <= 0.0 {
+                    return Color::ZERO;
+                }
+
+                let x = temperature.value(point) / max * BLACKBODY_STEPS as f64;
+                let i = (x as usize).min(BLACKBODY_STEPS - 1);
+                let f = x - i as f64;
+
+                (1.0 - f) * self.blackbody[i] + f * self.blackbody[i + 1]
+            }
+        }
+    }
+}
+
+impl Hittable for GridMedium {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut ray_t = ray_t;
+        if self.majorant <= 0.0 || !self.density.bounding_box().hit(ray, &mut ray_t) {
+            return None;
+        }
+
+        // Distances are sampled in units of t, which the direction's length scales
+        let step = 1.0 / (self.majorant * ray.direction.length());
+        let mut t = ray_t.min;
+
+        loop {
+            t -= step * (1.0 - random::<f64>()).ln();
+            if t >= ray_t.max {
+                return None;
+            }
+
+            let point = ray.at(t);
+            if random::<f64>() * self.majorant >= self.density_scale * self.density.value(point) {
+                continue;
+            }
+
+            let emitted = self.emitted(point);
+            let material: Arc<dyn Material + Send> = if emitted.length_squared() > 0.0 {
+                Arc::new(EmittingPhase { phase_function: Arc::clone(&self.phase_function), emitted })
+            } else {
+                Arc::clone(&self.phase_function)
+            };
+
+            return Some(HitRecord {
+                point,
+                normal: Vec3::new(1.0, 0.0, 0.0),
+                material,
+                t,
+                u: 0.0,
+                v: 0.0,
+                color: None,
+                front_face: true,
+            });
+        }
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.density.bounding_box()
+    }
+
+    // Estimated without bias by ratio tracking: every tentative collision multiplies in the chance of
+    // it not being real
+    fn transmittance(&self, ray: &Ray, ray_t: Interval) -> f64 {
+        let mut ray_t = ray_t;
+        if self.majorant <= 0.0 || !self.density.bounding_box().hit(ray, &mut ray_t) {
+            return 1.0;
+        }
+
+        let step = 1.0 / (self.majorant * ray.direction.length());
+        let mut transmittance = 1.0;
+        let mut t = ray_t.min;
+
+        loop {
+            t -= step * (1.0 - random::<f64>()).ln();
+            if t >= ray_t.max {
+                return transmittance;
+            }
+
+            transmittance *= 1.0 - self.density_scale * self.density.value(ray.at(t)) / self.majorant;
+        }
+    }
+}
+
+// Phase function of a collision in a glowing region, adding the local emission
+struct EmittingPhase {
+    phase_function: Arc<dyn Material + Send>,
+    emitted: Color,
+}
+
+impl Material for EmittingPhase {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatterRecord> {
+        self.phase_function.scatter(ray, hit)
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.phase_function.eval(ray, hit, direction)
+    }
+
+    fn emitted(&self, _hit: &HitRecord) -> Color {
+        self.emitted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HittableList;
+    use crate::material::{Isotropic, Lambertian};
+    use crate::sphere::Sphere;
+
+    fn smoke() -> Arc<dyn Material + Send> {
+        Arc::new(Isotropic::new(Color::splat(0.5)))
+    }
+
+    // Along the x axis from x = -5, reaching x = 5 at t = 1
+    fn ray() -> Ray {
+        Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(10.0, 0.0, 0.0), 0.0)
+    }
+
+    #[test]
+    fn constant_medium_transmittance_is_exact() {
+        let boundary = Arc::new(Sphere::new(Vec3::ZERO, 1.0, smoke()));
+        let medium = ConstantMedium::new(boundary, 0.5, smoke());
+
+        let through = medium.transmittance(&ray(), Interval::new(0.0, 1.0));
+        assert!((through - (-0.5 * 2.0f64).exp()).abs() < 1e-9, "{through}");
+
+        // Stopping halfway only passes through half the sphere
+        let half = medium.transmittance(&ray(), Interval::new(0.0, 0.5));
+        assert!((half - (-0.5f64).exp()).abs() < 1e-9, "{half}");
+
+        assert_eq!(medium.transmittance(&ray(), Interval::new(0.0, 0.3)), 1.0);
+    }
+
+    #[test]
+    fn grid_medium_transmittance_averages_to_beer_lambert() {
+        let bbox = AABB::new(Interval::new(-1.0, 1.0), Interval::new(-1.0, 1.0), Interval::new(-1.0, 1.0));
+        let grid = VoxelGrid::new([2, 2, 2], vec![1.0; 8], bbox).unwrap();
+        let medium = GridMedium::new(grid, 0.75, smoke());
+
+        crate::rng::seed(1);
+        let samples = 20_000;
+        let mean = (0..samples).map(|_| medium.transmittance(&ray(), Interval::new(0.0, 1.0))).sum::<f64>() / samples as f64;
+        assert!((mean - (-0.75 * 2.0f64).exp()).abs() < 0.01, "{mean}");
+    }
+
+    #[test]
+    fn surfaces_block_and_media_multiply() {
+        let boundary = Arc::new(Sphere::new(Vec3::ZERO, 1.0, smoke()));
+        let mut list = HittableList { objects: vec![] };
+        list.add(ConstantMedium::new(boundary, 0.5, smoke()));
+        assert!((list.transmittance(&ray(), Interval::new(0.0, 1.0)) - (-1.0f64).exp()).abs() < 1e-9);
+
+        list.add(Sphere::new(Vec3::new(3.0, 0.0, 0.0), 0.5, Arc::new(Lambertian::new(Color::ONE))));
+        assert_eq!(list.transmittance(&ray(), Interval::new(0.0, 1.0)), 0.0);
+        assert!(list.transmittance(&ray(), Interval::new(0.0, 0.7)) > 0.0);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::aabb::AABB;
+use crate::interval::Interval;
+use crate::vec3::Vec3;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+// Scalar field sampled on a regular 3D grid of points spanning a box, such as the density of a
+// cloud or the temperature of a fire
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    // x varies fastest, then y, then z
+    values: Vec<f32>,
+    bbox: AABB,
+    max: f64,
+}
+
+impl VoxelGrid {
+    // None if the number of values doesn't match the resolution or a side has no points
+    pub fn new(resolution: [usize; 3], values: Vec<f32>, bbox: AABB) -> Option<Self> {
+        if resolution.contains(&0) || values.len() != resolution.iter().product::<usize>() {
+            return None;
+        }
+
+        let max = values.iter().fold(0.0f32, |max, &value| max.max(value)) as f64;
+
+        Some(Self {
+            resolution,
+            values,
+            bbox,
+            max,
+        })
+    }
+
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    // Largest value in the grid, or zero if every value is negative
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    fn at(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[(z * ny + y) * nx + x] as f64
+    }
+
+    // Trilinear interpolation between the grid points around point, zero outside the box.
+    // Negative values are treated as zero.
+    pub fn value(&self, point: Vec3) -> f64 {
+        let axes = [(self.bbox.x, point.x), (self.bbox.y, point.y), (self.bbox.z, point.z)];
+        if axes.iter().any(|(interval, p)| !interval.contains(*p)) {
+            return 0.0;
+        }
+
+        // Continuous grid coordinates of point, split into the lower corner index and the offset
+        let mut corner = [0; 3];
+        let mut offset = [0.0; 3];
+        for (i, (interval, p)) in axes.iter().enumerate() {
+            let cells = self.resolution[i] - 1;
+            let local = if interval.size() > 0.0 { (p - interval.min) / interval.size() * cells as f64 } else { 0.0 };
+
+            corner[i] = (local as usize).min(cells.saturating_sub(1));
+            offset[i] = if cells == 0 { 0.0 } else { local - corner[i] as f64 };
+        }
+
+        let mut value = 0.0;
+        for dz in 0..2 {
+            for dy in 0..2 {
+                for dx in 0..2 {
+                    let weight = [dx, dy, dz].iter().enumerate()
+                        .map(|(i, &d)| if d == 0 { 1.0 - offset[i] } else { offset[i] })
+                        .product::<f64>();
+
+                    if weight > 0.0 {
+                        let index = |i: usize, d: usize| (corner[i] + d).min(self.resolution[i] - 1);
+                        value += weight * self.at(index(0, dx), index(1, dy), index(2, dz)).max(0.0);
+                    }
+                }
+            }
+        }
+
+        value
+    }
+}
+
+// Reads a grid in the Mitsuba .vol format: the bytes "VOL" and version 3, then little endian
+// encoding, x, y and z resolution and channel count as 32 bit integers, the bounding box as six
+// 32 bit floats (min x, y, z, then max) and finally the values with x varying fastest. Only single
+// channel grids of 32 bit floats or 8 bit values (read as 0 to 1) are supported.
+pub fn load_vol(path: &Path) -> io::Result<VoxelGrid> {
+    read_vol(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn read_vol(input: &mut dyn Read) -> io::Result<VoxelGrid> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic).map_err(truncated)?;
+    if &magic[..3] != b"VOL" {
+        return Err(invalid_data("missing VOL signature".to_string()));
+    }
+    if magic[3] != 3 {
+        return Err(invalid_data(format!("unsupported VOL version {}", magic[3])));
+    }
+
+    let encoding = read_i32(input)?;
+    let resolution = [read_i32(input)?, read_i32(input)?, read_i32(input)?];
+    let channels = read_i32(input)?;
+
+    if resolution.iter().any(|&n| n <= 0) {
+        return Err(invalid_data(format!("invalid VOL resolution {resolution:?}")));
+    }
+    if channels != 1 {
+        return Err(invalid_data(format!("unsupported VOL channel count {channels}, expected 1")));
+    }
+
+    let mut bounds = [0.0; 6];
+    for bound in &mut bounds {
+        *bound = read_f32(input)? as f64;
+    }
+    let bbox = AABB::new(
+        Interval::new(bounds[0], bounds[3]),
+        Interval::new(bounds[1], bounds[4]),
+        Interval::new(bounds[2], bounds[5]),
+    );
+    if bbox.x.size() < 0.0 || bbox.y.size() < 0.0 || bbox.z.size() < 0.0 {
+        return Err(invalid_data("VOL bounding box has min above max".to_string()));
+    }
+
+    let size = match encoding {
+        1 => 4,
+        3 => 1,
+        _ => return Err(invalid_data(format!("unsupported VOL encoding {encoding}"))),
+    };
+
+    let resolution = resolution.map(|n| n as usize);
+    let length = resolution.iter().try_fold(size, |length: usize, &n| length.checked_mul(n))
+        .ok_or_else(|| invalid_data(format!("VOL resolution {resolution:?} is too large")))?;
+
+    // Read rather than allocated up front, so a corrupt header can't ask for huge amounts of memory
+    let mut bytes = vec![];
+    input.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(invalid_data("unexpected end of VOL file".to_string()));
+    }
+
+    let values = match size {
+        4 => bytes.chunks_exact(4).map(|value| f32::from_le_bytes(value.try_into().unwrap())).collect(),
+        _ => bytes.iter().map(|&value| value as f32 / 255.0).collect(),
+    };
+
+    VoxelGrid::new(resolution, values, bbox).ok_or_else(|| invalid_data("VOL value count doesn't match its resolution".to_string()))
+}
+
+fn read_i32(input: &mut dyn Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes).map_err(truncated)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn read_f32(input: &mut dyn Read) -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes).map_err(truncated)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn truncated(err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof => invalid_data("unexpected end of VOL file".to_string()),
+        _ => err,
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vol(encoding: i32, resolution: [i32; 3], bounds: [f32; 6], values: &[u8]) -> Vec<u8> {
+        let mut bytes = b"VOL\x03".to_vec();
+        for n in [encoding, resolution[0], resolution[1], resolution[2], 1] {
+            bytes.extend(n.to_le_bytes());
+        }
+        bytes.extend(bounds.iter().flat_map(|b| b.to_le_bytes()));
+        bytes.extend(values);
+        bytes
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    const UNIT: [f32; 6] = [0.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+
+    fn error(bytes: &[u8]) -> String {
+        match read_vol(&mut &bytes[..]) {
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => err.to_string(),
+            Err(err) => panic!("expected invalid data, got `{err}`"),
+            Ok(_) => panic!("expected the grid to be rejected"),
+        }
+    }
+
+    #[test]
+    fn reads_float_grid() {
+        let values = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let grid = read_vol(&mut vol(1, [2, 2, 2], UNIT, &floats(&values)).as_slice()).unwrap();
+
+        assert_eq!(grid.resolution(), [2, 2, 2]);
+        assert_eq!(grid.max(), 7.0);
+        // x varies fastest, then y, then z
+        assert_eq!(grid.value(Vec3::new(1.0, 0.0, 0.0)), 1.0);
+        assert_eq!(grid.value(Vec3::new(0.0, 1.0, 0.0)), 2.0);
+        assert_eq!(grid.value(Vec3::new(0.0, 0.0, 1.0)), 4.0);
+        assert_eq!(grid.value(Vec3::new(0.5, 0.5, 0.5)), 3.5);
+        assert_eq!(grid.value(Vec3::new(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn reads_byte_grid() {
+        let grid = read_vol(&mut vol(3, [2, 1, 1], [-1.0, 0.0, 0.0, 1.0, 0.0, 0.0], &[0, 255]).as_slice()).unwrap();
+
+        assert_eq!(grid.max(), 1.0);
+        assert_eq!(grid.value(Vec3::new(0.0, 0.0, 0.0)), 0.5);
+        assert_eq!(grid.bounding_box().x.min, -1.0);
+    }
+
+    #[test]
+    fn negative_values_read_as_zero() {
+        let grid = read_vol(&mut vol(1, [2, 1, 1], UNIT, &floats(&[-4.0, 2.0])).as_slice()).unwrap();
+
+        assert_eq!(grid.max(), 2.0);
+        assert_eq!(grid.value(Vec3::new(0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(grid.value(Vec3::new(0.5, 0.0, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert_eq!(error(b"VOX\x03"), "missing VOL signature");
+        assert_eq!(error(b"VOL\x02"), "unsupported VOL version 2");
+        assert_eq!(error(&vol(1, [2, 0, 2], UNIT, &[])), "invalid VOL resolution [2, 0, 2]");
+        assert_eq!(error(&vol(2, [1, 1, 1], UNIT, &[0; 4])), "unsupported VOL encoding 2");
+        assert_eq!(error(&vol(1, [1, 1, 1], [1.0, 0.0, 0.0, 0.0, 1.0, 1.0], &[0; 4])), "VOL bounding box has min above max");
+
+        let mut channels = vol(1, [1, 1, 1], UNIT, &[0; 12]);
+        channels[20..24].copy_from_slice(&3i32.to_le_bytes());
+        assert_eq!(error(&channels), "unsupported VOL channel count 3, expected 1");
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        assert_eq!(error(b"VOL\x03\x01\x00"), "unexpected end of VOL file");
+        assert_eq!(error(&vol(1, [2, 2, 2], UNIT, &floats(&[0.0; 7]))), "unexpected end of VOL file");
+
+        // A huge resolution on a short file fails without trying to allocate the whole grid
+        assert_eq!(error(&vol(1, [1 << 20, 1 << 20, 1 << 10], UNIT, &[0; 16])), "unexpected end of VOL file");
+    }
+
+    #[test]
+    fn rejects_oversized_resolution() {
+        assert_eq!(
+            error(&vol(1, [i32::MAX, i32::MAX, 3], UNIT, &[0; 16])),
+            "VOL resolution [2147483647, 2147483647, 3] is too large",
+        );
+    }
+
+    #[test]
+    fn new_checks_value_count() {
+        let bbox = AABB::new(Interval::new(0.0, 1.0), Interval::new(0.0, 1.0), Interval::new(0.0, 1.0));
+        assert!(VoxelGrid::new([2, 1, 1], vec![0.0; 3], bbox).is_none());
+        assert!(VoxelGrid::new([0, 1, 1], vec![], bbox).is_none());
+        assert!(VoxelGrid::new([3, 1, 1], vec![0.0; 3], bbox).is_some());
+    }
+}